@@ -0,0 +1,35 @@
+#[cfg(feature = "std")]
+use fslock::{LockFile, StalePolicy};
+#[cfg(feature = "std")]
+use std::{env, fs::OpenOptions, io::Write, process, thread, time::Duration};
+
+#[cfg(feature = "std")]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let (path, log_path) = match (args.next(), args.next(), args.next()) {
+        (Some(path), Some(log_path), None) => (path, log_path),
+        _ => {
+            eprintln!("Expected two arguments");
+            process::exit(1);
+        },
+    };
+
+    let mut lockfile = LockFile::open(&path)?;
+    let broke = lockfile.lock_or_break_stale(StalePolicy::dead_owner())?;
+
+    let mut log =
+        OpenOptions::new().create(true).append(true).open(log_path)?;
+    writeln!(log, "start {}", process::id())?;
+    thread::sleep(Duration::from_millis(100));
+    writeln!(log, "end {}", process::id())?;
+    lockfile.unlock()?;
+
+    println!("{}", if broke { "broke" } else { "waited" });
+
+    Ok(())
+}
+
+#[cfg(not(feature = "std"))]
+fn main() {}
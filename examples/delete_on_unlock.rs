@@ -0,0 +1,43 @@
+#[cfg(feature = "std")]
+use fslock::LockFileOptions;
+#[cfg(feature = "std")]
+use std::{env, fs, io::Write, process, thread, time::Duration};
+
+#[cfg(feature = "std")]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let (path, log_path, rounds) =
+        match (args.next(), args.next(), args.next(), args.next()) {
+            (Some(path), Some(log_path), Some(rounds), None) => {
+                (path, log_path, rounds)
+            },
+            _ => {
+                eprintln!("Expected three arguments");
+                process::exit(1);
+            },
+        };
+    let rounds = rounds.parse::<u32>().expect("Invalid number of rounds");
+
+    let mut options = LockFileOptions::new();
+    options.delete_on_unlock(true);
+    for _ in 0 .. rounds {
+        let mut lockfile = options.open(&path)?;
+        lockfile.lock()?;
+
+        let mut log =
+            fs::OpenOptions::new().create(true).append(true).open(&log_path)?;
+        writeln!(log, "start {}", process::id())?;
+        thread::sleep(Duration::from_millis(1));
+        writeln!(log, "end {}", process::id())?;
+        lockfile.unlock()?;
+    }
+
+    println!("DONE");
+
+    Ok(())
+}
+
+#[cfg(not(feature = "std"))]
+fn main() {}
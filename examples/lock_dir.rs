@@ -0,0 +1,35 @@
+#[cfg(feature = "std")]
+use fslock::LockDir;
+#[cfg(feature = "std")]
+use std::{env, fs::OpenOptions, io::Write, process, thread, time::Duration};
+
+#[cfg(feature = "std")]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let (path, log_path) = match (args.next(), args.next(), args.next()) {
+        (Some(path), Some(log_path), None) => (path, log_path),
+        _ => {
+            eprintln!("Expected two arguments");
+            process::exit(1);
+        },
+    };
+
+    let mut lockdir = LockDir::open(&path)?;
+    lockdir.lock()?;
+
+    let mut log =
+        OpenOptions::new().create(true).append(true).open(log_path)?;
+    writeln!(log, "start {}", process::id())?;
+    thread::sleep(Duration::from_millis(20));
+    writeln!(log, "end {}", process::id())?;
+    lockdir.unlock()?;
+
+    println!("DONE");
+
+    Ok(())
+}
+
+#[cfg(not(feature = "std"))]
+fn main() {}
@@ -0,0 +1,29 @@
+#[cfg(feature = "std")]
+use fslock::{Backend, LockFileOptions};
+#[cfg(feature = "std")]
+use std::{env, process};
+
+#[cfg(feature = "std")]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let path = match args.next() {
+        Some(arg) if args.next().is_none() => arg,
+        _ => {
+            eprintln!("Expected one argument");
+            process::exit(1);
+        },
+    };
+
+    let mut lockfile =
+        LockFileOptions::new().backend(Backend::Sentinel).open(&path)?;
+    lockfile.lock()?;
+    println!("LOCKED");
+
+    // Exits without running destructors, as if the process crashed.
+    process::exit(0);
+}
+
+#[cfg(not(feature = "std"))]
+fn main() {}
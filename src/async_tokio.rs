@@ -0,0 +1,91 @@
+//! This module implements lock acquisition for the tokio runtime.
+
+use crate::{timeout::Backoff, Error, LockFile, LockGuard, LockMode};
+
+impl LockFile {
+    /// Locks this file without blocking the async runtime, returning a guard
+    /// which unlocks it when dropped. Waits while it is not possible to lock
+    /// (i.e. someone else already owns a lock).
+    ///
+    /// The lock is acquired by polling [`LockFile::try_lock`] with an
+    /// exponential backoff, sleeping through tokio's timer between attempts,
+    /// so the runtime must have its time driver enabled. The future is
+    /// cancellation-safe: if it is dropped before completion, this handle does
+    /// not own the lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// # let runtime = tokio::runtime::Builder::new_current_thread()
+    /// #     .enable_time()
+    /// #     .build()?;
+    /// # runtime.block_on(async {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_async.lock")?;
+    /// let guard = file.lock_async().await?;
+    /// do_stuff().await;
+    /// guard.unlock()?;
+    ///
+    /// # Ok(())
+    /// # })
+    /// # }
+    /// # async fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub async fn lock_async(&mut self) -> Result<LockGuard<'_>, Error> {
+        self.lock_async_with_mode(LockMode::Exclusive).await?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Locks this file in shared mode without blocking the async runtime,
+    /// returning a guard which unlocks it when dropped. Waits while it is not
+    /// possible to lock (i.e. someone else owns an exclusive lock). See
+    /// [`LockFile::lock_async`] for details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub async fn lock_shared_async(&mut self) -> Result<LockGuard<'_>, Error> {
+        self.lock_async_with_mode(LockMode::Shared).await?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Locks this file and writes this process's PID into it without blocking
+    /// the async runtime, returning a guard which unlocks it when dropped. See
+    /// [`LockFile::lock_async`] and [`LockFile::lock_with_pid`] for details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub async fn lock_with_pid_async(
+        &mut self,
+    ) -> Result<LockGuard<'_>, Error> {
+        self.lock_async_with_mode(LockMode::Exclusive).await?;
+        self.write_pid()?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Polls for a lock in the given mode, sleeping asynchronously between
+    /// attempts.
+    async fn lock_async_with_mode(
+        &mut self,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        self.check_unlocked()?;
+        let mut backoff = Backoff::new();
+        loop {
+            // Acquiring and recording the lock happen in the same poll, so
+            // dropping the future can never leave the lock held.
+            if self.try_acquire(mode)? {
+                return Ok(());
+            }
+            if let Some(delay) = backoff.next_delay(None) {
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
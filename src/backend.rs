@@ -0,0 +1,960 @@
+//! This module implements the mechanisms used to lock files, and the selection
+//! of one of them.
+
+use crate::{
+    owner::write_owner_record,
+    sys,
+    Error,
+    LockFileOptions,
+    LockMode,
+    OsStr,
+};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+};
+
+/// Shortest sleep between two attempts to lock, in milliseconds, for backends
+/// which cannot block, and for locking with a timeout.
+pub(crate) const MIN_POLL_MS: u32 = 1;
+
+/// Longest sleep between two attempts to lock, in milliseconds, for backends
+/// which cannot block, and for locking with a timeout.
+pub(crate) const MAX_POLL_MS: u32 = 50;
+
+/// Suffix of the path of the file holding the owner's content inside a lock
+/// directory.
+const DIR_CONTENT_SUFFIX: &str = "/owner";
+
+/// Suffix of the path a stale lock is renamed to before being removed, for
+/// backends whose lock is the lock file itself.
+const STALE_SUFFIX: &str = ".break.old";
+
+/// Features supported by a [`LockBackend`].
+///
+/// # Example
+///
+/// ```
+/// use fslock::Backend;
+///
+/// assert!(Backend::Native.capabilities().shared);
+/// assert!(!Backend::Sentinel.capabilities().shared);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities {
+    /// Whether shared locks are supported. Otherwise, locking in shared mode
+    /// fails.
+    pub shared: bool,
+    /// Whether byte-range locks are supported, through
+    /// [`LockFile::lock_range`](crate::LockFile::lock_range) and its variants.
+    pub ranges: bool,
+    /// Whether locks are held per handle. Otherwise, they are held per
+    /// process, and handles in the same process do not exclude each other.
+    pub per_handle: bool,
+    /// Whether locks work over NFS.
+    pub nfs_safe: bool,
+    /// Whether converting a held lock between modes is atomic, in which case a
+    /// failed conversion keeps the lock in its previous mode.
+    pub atomic_conversion: bool,
+}
+
+/// The lock file a [`LockBackend`] operates on: its path, and the file kept
+/// open by the backend, if any.
+#[derive(Debug)]
+pub struct LockTarget<'file> {
+    /// Path of the lock file.
+    path: &'file OsStr,
+    /// Options the lock file was opened with.
+    options: &'file LockFileOptions,
+    /// The open lock file, if any.
+    desc: &'file mut Option<sys::FileDesc>,
+}
+
+impl<'file> LockTarget<'file> {
+    /// Creates a target over the given path, options and open file.
+    pub(crate) fn new(
+        path: &'file OsStr,
+        options: &'file LockFileOptions,
+        desc: &'file mut Option<sys::FileDesc>,
+    ) -> Self {
+        Self { path, options, desc }
+    }
+
+    /// Returns the path of the lock file, as given when opening it.
+    pub fn path(&self) -> &OsStr {
+        self.path
+    }
+
+    /// Returns the options the lock file was opened with.
+    pub fn options(&self) -> &LockFileOptions {
+        self.options
+    }
+
+    /// Returns the file kept open by the backend, if any: a file descriptor on
+    /// Unix and a handle on Windows.
+    pub fn desc(&self) -> Option<sys::FileDesc> {
+        *self.desc
+    }
+
+    /// Returns the file kept open by the backend, failing if there is none.
+    pub fn require_desc(&self) -> Result<sys::FileDesc, Error> {
+        self.desc.ok_or_else(sys::not_open)
+    }
+
+    /// Replaces the file kept open by the backend. The previous one, if any, is
+    /// not closed.
+    pub fn set_desc(&mut self, desc: Option<sys::FileDesc>) {
+        *self.desc = desc;
+    }
+
+    /// Runs the given operation on this target, handling interruptions as
+    /// configured by the [`InterruptPolicy`](crate::InterruptPolicy) of its
+    /// options.
+    pub(crate) fn interruptible<F, T>(
+        &mut self,
+        mut operation: F,
+    ) -> Result<T, Error>
+    where
+        F: FnMut(&mut Self) -> Result<T, Error>,
+    {
+        let policy = self.options.interrupt;
+        policy.run(|| operation(self))
+    }
+}
+
+/// A mechanism to lock files, selected through [`Backend`]. Every method but
+/// [`LockBackend::capabilities`], [`LockBackend::lock`],
+/// [`LockBackend::try_lock`] and [`LockBackend::unlock`] has a default
+/// implementation, suited for backends locking a file kept open since the
+/// [`LockFile`](crate::LockFile) was opened.
+///
+/// A [`LockFile`](crate::LockFile) keeps track of whether it owns a lock, and
+/// so a backend is only asked to lock while not holding a lock, and to unlock
+/// or convert a lock it holds. Byte-range locks are not tracked.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{
+///     Backend,
+///     Capabilities,
+///     LockBackend,
+///     LockFileOptions,
+///     LockMode,
+///     LockTarget,
+/// };
+/// use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+///
+/// /// Counts the locks acquired through the native backend.
+/// #[derive(Debug)]
+/// struct Counting(AtomicUsize);
+///
+/// impl LockBackend for Counting {
+///     fn capabilities(&self) -> Capabilities {
+///         Backend::Native.capabilities()
+///     }
+///
+///     fn lock(
+///         &self,
+///         file: &mut LockTarget,
+///         mode: LockMode,
+///     ) -> Result<(), fslock::Error> {
+///         Backend::Native.implementation().lock(file, mode)?;
+///         self.0.fetch_add(1, SeqCst);
+///         Ok(())
+///     }
+///
+///     fn try_lock(
+///         &self,
+///         file: &mut LockTarget,
+///         mode: LockMode,
+///     ) -> Result<bool, fslock::Error> {
+///         let locked = Backend::Native.implementation().try_lock(file, mode)?;
+///         if locked {
+///             self.0.fetch_add(1, SeqCst);
+///         }
+///         Ok(locked)
+///     }
+///
+///     fn unlock(&self, file: &mut LockTarget) -> Result<(), fslock::Error> {
+///         Backend::Native.implementation().unlock(file)
+///     }
+/// }
+///
+/// static COUNTING: Counting = Counting(AtomicUsize::new(0));
+///
+/// let mut file = LockFileOptions::new()
+///     .backend(Backend::Custom(&COUNTING))
+///     .open("testfiles/custom_backend.lock")?;
+/// file.lock()?;
+/// file.unlock()?;
+/// assert!(file.try_lock()?);
+/// assert_eq!(COUNTING.0.load(SeqCst), 2);
+///
+/// # Ok(())
+/// # }
+/// ```
+pub trait LockBackend: fmt::Debug + Send + Sync {
+    /// Returns the features supported by this backend.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Opens the lock file at the given path with the given options, when a
+    /// [`LockFile`](crate::LockFile) is opened. Returns `None` if this backend
+    /// does not keep a file open while unlocked. By default, opens the file
+    /// as configured by the options, syncing the directory containing it if
+    /// it may be created and the options are
+    /// [durable](crate::LockFileOptions::durable).
+    fn open(
+        &self,
+        path: &OsStr,
+        options: &LockFileOptions,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        let desc = sys::open_with(path, options)?;
+        if options.durable && (options.create || options.create_new) {
+            if let Err(error) = sys::sync_parent(path) {
+                sys::close(desc);
+                return Err(error);
+            }
+        }
+        Ok(Some(desc))
+    }
+
+    /// Opens for reading the file holding the content written by the owner of
+    /// the lock at the given path, when no file is kept open. Returns `None` if
+    /// there is no such file. By default, opens the lock file itself.
+    fn open_content(
+        &self,
+        path: &OsStr,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        sys::open_read_existing(path)
+    }
+
+    /// Locks the given file in the given mode, blocking while it is not
+    /// possible to lock.
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error>;
+
+    /// Locks the given file in the given mode, returning as soon as possible if
+    /// it is not possible to lock. Returns whether it was locked.
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error>;
+
+    /// Unlocks the given file.
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error>;
+
+    /// Converts the shared lock held on the given file into an exclusive one,
+    /// blocking while it is not possible. By default, unlocks and then locks
+    /// exclusively.
+    fn upgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.unlock(file)?;
+        self.lock(file, LockMode::Exclusive)
+    }
+
+    /// Converts the shared lock held on the given file into an exclusive one,
+    /// returning as soon as possible if it is not possible. Returns whether it
+    /// was converted. By default, unlocks and then tries to lock exclusively.
+    fn try_upgrade(&self, file: &mut LockTarget) -> Result<bool, Error> {
+        self.unlock(file)?;
+        self.try_lock(file, LockMode::Exclusive)
+    }
+
+    /// Converts the exclusive lock held on the given file into a shared one.
+    /// By default, unlocks and then locks in shared mode.
+    fn downgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.unlock(file)?;
+        self.lock(file, LockMode::Shared)
+    }
+
+    /// Locks the given region of the given file, blocking while it is not
+    /// possible to lock. Not supported by default.
+    fn lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        let _ = (file, offset, len, mode);
+        Err(sys::unsupported())
+    }
+
+    /// Locks the given region of the given file, returning as soon as possible
+    /// if it is not possible to lock. Returns whether it was locked. Not
+    /// supported by default.
+    fn try_lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        let _ = (file, offset, len, mode);
+        Err(sys::unsupported())
+    }
+
+    /// Unlocks the given region of the given file. Not supported by default.
+    fn unlock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), Error> {
+        let _ = (file, offset, len);
+        Err(sys::unsupported())
+    }
+
+    /// Syncs the content just written into the given file, such as owner
+    /// information, to disk. Errors are only reported to the caller if the
+    /// file is [durable](crate::LockFileOptions::durable). By default, syncs
+    /// the file kept open.
+    fn sync(&self, file: &mut LockTarget) -> Result<(), Error> {
+        sys::fsync(file.require_desc()?)
+    }
+
+    /// Removes the lock at the given path, whose owner is considered stale, for
+    /// backends keeping no file open while unlocked (see
+    /// [`LockBackend::open`]). Called by
+    /// [`LockFile::lock_or_break_stale`](crate::LockFile::lock_or_break_stale)
+    /// while no other caller of it may acquire the lock. Returns whether there
+    /// was a lock to remove. Not supported by default.
+    fn remove_stale(&self, path: &OsStr) -> Result<bool, Error> {
+        let _ = path;
+        Err(sys::unsupported())
+    }
+
+    /// Closes the given file when the [`LockFile`](crate::LockFile) is dropped,
+    /// after unlocking it. By default, closes the file kept open, if any.
+    fn close(&self, file: &mut LockTarget) {
+        if let Some(desc) = file.desc.take() {
+            sys::close(desc);
+        }
+    }
+}
+
+/// Mechanism used by a [`LockFile`](crate::LockFile) to lock its file, chosen
+/// through [`LockFileOptions::backend`] when opening it.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Backend {
+    /// `flock` on Unix and `LockFileEx` on Windows, the default. Locks are
+    /// held per handle. On some Unix systems, `flock` does not work over
+    /// network file systems such as NFS.
+    #[default]
+    Native,
+    /// Open file description locks (`F_OFD_SETLK`), available on Linux and
+    /// Android only. Unlike classic POSIX `fcntl` locks, they are held per
+    /// handle, and unlike `flock`, they work over NFS and convert between
+    /// lock modes atomically (see
+    /// [`LockFile::upgrade`](crate::LockFile::upgrade)).
+    ///
+    /// A lock is a byte-range lock over the whole file, in the same lock space
+    /// as [`LockFile::lock_range`](crate::LockFile::lock_range). Thus, it
+    /// conflicts with range locks held by other handles, while range locks
+    /// held by the same handle merge with it and are released along with it.
+    ///
+    /// Open file description locks have no deadlock detection: handles
+    /// waiting for each other block forever. In particular, two handles
+    /// sharing the lock which both call
+    /// [`LockFile::upgrade`](crate::LockFile::upgrade) deadlock, see its
+    /// documentation.
+    Ofd,
+    /// Classic POSIX record locks (`F_SETLK`), available on Unix only. They
+    /// work over NFS and convert between lock modes atomically, but they are
+    /// held per process: handles in the same process do not exclude each
+    /// other, and closing any handle to the file releases every lock the
+    /// process holds on it. Like with [`Backend::Ofd`], a lock is a byte-range
+    /// lock over the whole file.
+    Posix,
+    /// The lock is the existence of the lock file, like with `lockfile(1)`:
+    /// locking atomically creates it with `O_CREAT | O_EXCL` (`CREATE_NEW` on
+    /// Windows) and writes the [owner information](crate#owner-information)
+    /// into it, and unlocking removes it. This works on file systems where
+    /// advisory locks are unreliable or ignored. Only exclusive locks are
+    /// supported, and blocking locks poll.
+    ///
+    /// Since the lock is not held by the operating system, it is not released
+    /// when its owner crashes: the file is left behind, and nobody can lock
+    /// until it is removed by hand. The recorded owner, read with
+    /// [`LockFile::read_owner_pid`](crate::LockFile::read_owner_pid) or
+    /// [`LockFile::is_owner_alive`](crate::LockFile::is_owner_alive), tells
+    /// whether it is safe to remove, and
+    /// [`LockFile::lock_or_break_stale`](crate::LockFile::lock_or_break_stale)
+    /// removes it if its owner is stale.
+    Sentinel,
+    /// The lock is the existence of a directory at the lock path: locking
+    /// creates it, which is atomic even on old NFS versions, and unlocking
+    /// removes it. The content written by the owner, starting with the [owner
+    /// information](crate#owner-information) recorded when locking, is kept
+    /// in a file named `owner` inside the directory. Only exclusive locks are
+    /// supported, and blocking locks poll. [`LockDir`](crate::LockDir) wraps
+    /// a lock file using this backend.
+    ///
+    /// Like with [`Backend::Sentinel`], a crashed owner leaves the directory
+    /// behind, unless it is removed by
+    /// [`LockFile::lock_or_break_stale`](crate::LockFile::lock_or_break_stale).
+    Mkdir,
+    /// A custom backend.
+    Custom(&'static dyn LockBackend),
+}
+
+impl Backend {
+    /// Returns whether this backend is available on the current platform.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fslock::Backend;
+    ///
+    /// assert!(Backend::Native.is_available());
+    /// assert_eq!(Backend::Posix.is_available(), cfg!(unix));
+    /// ```
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Ofd => {
+                cfg!(any(target_os = "linux", target_os = "android"))
+            },
+            Backend::Posix => cfg!(unix),
+            _ => true,
+        }
+    }
+
+    /// Returns the features supported by this backend.
+    pub fn capabilities(self) -> Capabilities {
+        self.implementation().capabilities()
+    }
+
+    /// Returns the implementation of this backend. The implementation of a
+    /// backend not available on the current platform fails on every
+    /// operation.
+    pub fn implementation(self) -> &'static dyn LockBackend {
+        match self {
+            Backend::Native => &NativeBackend,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Backend::Ofd => &OfdBackend,
+            #[cfg(unix)]
+            Backend::Posix => &PosixBackend,
+            Backend::Sentinel => &SentinelBackend,
+            Backend::Mkdir => &MkdirBackend,
+            Backend::Custom(backend) => backend,
+            #[allow(unreachable_patterns)]
+            _ => &UnavailableBackend,
+        }
+    }
+}
+
+// Custom backends are compared by address, as they have no other identity.
+impl PartialEq for Backend {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Backend::Custom(this), Backend::Custom(other)) => {
+                address(*this) == address(*other)
+            },
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Backend {}
+
+impl Hash for Backend {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        mem::discriminant(self).hash(state);
+        if let Backend::Custom(backend) = self {
+            address(*backend).hash(state);
+        }
+    }
+}
+
+/// Returns the address of the given backend, ignoring its vtable.
+fn address(backend: &dyn LockBackend) -> *const () {
+    backend as *const dyn LockBackend as *const ()
+}
+
+/// Calls the given function until it locks, sleeping between attempts with an
+/// exponential backoff.
+fn poll_lock<F>(mut try_lock: F) -> Result<(), Error>
+where
+    F: FnMut() -> Result<bool, Error>,
+{
+    let mut delay = MIN_POLL_MS;
+    while !try_lock()? {
+        sys::sleep_ms(delay);
+        delay = (delay * 2).min(MAX_POLL_MS);
+    }
+    Ok(())
+}
+
+/// Fails if the given mode is shared, for backends without shared locks.
+fn require_exclusive(mode: LockMode) -> Result<(), Error> {
+    match mode {
+        LockMode::Exclusive => Ok(()),
+        LockMode::Shared => Err(sys::unsupported()),
+    }
+}
+
+/// See [`Backend::Native`].
+#[derive(Debug)]
+struct NativeBackend;
+
+impl LockBackend for NativeBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            shared: true,
+            ranges: true,
+            per_handle: true,
+            nfs_safe: false,
+            atomic_conversion: false,
+        }
+    }
+
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error> {
+        sys::lock(file.require_desc()?, mode)
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        sys::try_lock(file.require_desc()?, mode)
+    }
+
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+        sys::unlock(file.require_desc()?)
+    }
+
+    fn upgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        sys::upgrade(file.require_desc()?)
+    }
+
+    fn try_upgrade(&self, file: &mut LockTarget) -> Result<bool, Error> {
+        sys::try_upgrade(file.require_desc()?)
+    }
+
+    fn downgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        sys::downgrade(file.require_desc()?)
+    }
+
+    fn lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        sys::lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn try_lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        sys::try_lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn unlock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), Error> {
+        sys::unlock_range(file.require_desc()?, offset, len)
+    }
+}
+
+/// See [`Backend::Ofd`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug)]
+struct OfdBackend;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl LockBackend for OfdBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            shared: true,
+            ranges: true,
+            per_handle: true,
+            nfs_safe: true,
+            atomic_conversion: true,
+        }
+    }
+
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error> {
+        self.lock_range(file, 0, 0, mode)
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        self.try_lock_range(file, 0, 0, mode)
+    }
+
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.unlock_range(file, 0, 0)
+    }
+
+    fn upgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.lock(file, LockMode::Exclusive)
+    }
+
+    fn try_upgrade(&self, file: &mut LockTarget) -> Result<bool, Error> {
+        self.try_lock(file, LockMode::Exclusive)
+    }
+
+    fn downgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.lock(file, LockMode::Shared)
+    }
+
+    fn lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        sys::lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn try_lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        sys::try_lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn unlock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), Error> {
+        sys::unlock_range(file.require_desc()?, offset, len)
+    }
+}
+
+/// See [`Backend::Posix`].
+#[cfg(unix)]
+#[derive(Debug)]
+struct PosixBackend;
+
+#[cfg(unix)]
+impl LockBackend for PosixBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            shared: true,
+            ranges: true,
+            per_handle: false,
+            nfs_safe: true,
+            atomic_conversion: true,
+        }
+    }
+
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error> {
+        self.lock_range(file, 0, 0, mode)
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        self.try_lock_range(file, 0, 0, mode)
+    }
+
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.unlock_range(file, 0, 0)
+    }
+
+    fn upgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.lock(file, LockMode::Exclusive)
+    }
+
+    fn try_upgrade(&self, file: &mut LockTarget) -> Result<bool, Error> {
+        self.try_lock(file, LockMode::Exclusive)
+    }
+
+    fn downgrade(&self, file: &mut LockTarget) -> Result<(), Error> {
+        self.lock(file, LockMode::Shared)
+    }
+
+    fn lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        sys::posix_lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn try_lock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        sys::posix_try_lock_range(file.require_desc()?, offset, len, mode)
+    }
+
+    fn unlock_range(
+        &self,
+        file: &mut LockTarget,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), Error> {
+        sys::posix_unlock_range(file.require_desc()?, offset, len)
+    }
+}
+
+/// Syncs the given file, just created to hold a lock and written, along with
+/// the directories containing the given created paths if the file is durable.
+/// Errors are ignored unless the file is durable.
+fn sync_created(
+    file: &LockTarget,
+    desc: sys::FileDesc,
+    created: &[&OsStr],
+) -> Result<(), Error> {
+    if !file.options().durable {
+        let _ = sys::fsync(desc);
+        return Ok(());
+    }
+    sys::fsync(desc)?;
+    for path in created {
+        sys::sync_parent(path)?;
+    }
+    Ok(())
+}
+
+/// See [`Backend::Sentinel`].
+#[derive(Debug)]
+struct SentinelBackend;
+
+impl LockBackend for SentinelBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            shared: false,
+            ranges: false,
+            per_handle: true,
+            nfs_safe: true,
+            atomic_conversion: false,
+        }
+    }
+
+    fn open(
+        &self,
+        _path: &OsStr,
+        _options: &LockFileOptions,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        Ok(None)
+    }
+
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error> {
+        poll_lock(|| self.try_lock(file, mode))
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        require_exclusive(mode)?;
+        let desc = match sys::create_new(file.path(), file.options())? {
+            Some(desc) => desc,
+            None => return Ok(false),
+        };
+        let result = write_owner_record(desc)
+            .and_then(|()| sync_created(file, desc, &[file.path()]));
+        if let Err(error) = result {
+            let _ = sys::remove(file.path());
+            sys::close(desc);
+            return Err(error);
+        }
+        file.set_desc(Some(desc));
+        Ok(true)
+    }
+
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+        let desc = file.require_desc()?;
+        // The lock may have been broken, and the path taken by a new owner.
+        let result = sys::same_file(file.path(), desc).and_then(|same| {
+            if same {
+                sys::remove(file.path())
+            } else {
+                Ok(())
+            }
+        });
+        sys::close(desc);
+        file.set_desc(None);
+        result
+    }
+
+    fn remove_stale(&self, path: &OsStr) -> Result<bool, Error> {
+        // Renaming frees the path even on Windows, where a removed file still
+        // open by its owner keeps its path taken.
+        let stale_path = path.with_suffix(STALE_SUFFIX)?;
+        if !sys::rename_existing(path, &stale_path)? {
+            return Ok(false);
+        }
+        sys::remove(&stale_path)?;
+        Ok(true)
+    }
+}
+
+/// See [`Backend::Mkdir`].
+#[derive(Debug)]
+struct MkdirBackend;
+
+impl LockBackend for MkdirBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            shared: false,
+            ranges: false,
+            per_handle: true,
+            nfs_safe: true,
+            atomic_conversion: false,
+        }
+    }
+
+    fn open(
+        &self,
+        _path: &OsStr,
+        _options: &LockFileOptions,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        Ok(None)
+    }
+
+    fn open_content(
+        &self,
+        path: &OsStr,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        sys::open_read_existing(&path.with_suffix(DIR_CONTENT_SUFFIX)?)
+    }
+
+    fn lock(&self, file: &mut LockTarget, mode: LockMode) -> Result<(), Error> {
+        poll_lock(|| self.try_lock(file, mode))
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        require_exclusive(mode)?;
+        let content_path = file.path().with_suffix(DIR_CONTENT_SUFFIX)?;
+        if !sys::create_dir(file.path(), file.options())? {
+            return Ok(false);
+        }
+        let desc = match sys::create_new(&content_path, file.options()) {
+            Ok(Some(desc)) => desc,
+            Ok(None) => {
+                let _ = sys::remove_dir(file.path());
+                return Ok(false);
+            },
+            Err(error) => {
+                let _ = sys::remove_dir(file.path());
+                return Err(error);
+            },
+        };
+        let result = write_owner_record(desc).and_then(|()| {
+            sync_created(file, desc, &[&content_path, file.path()])
+        });
+        if let Err(error) = result {
+            let _ = sys::remove(&content_path);
+            let _ = sys::remove_dir(file.path());
+            sys::close(desc);
+            return Err(error);
+        }
+        file.set_desc(Some(desc));
+        Ok(true)
+    }
+
+    fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+        let desc = file.require_desc()?;
+        let content_path = file.path().with_suffix(DIR_CONTENT_SUFFIX)?;
+        // The lock may have been broken, and the path taken by a new owner.
+        let result = sys::same_file(&content_path, desc).and_then(|same| {
+            if same {
+                sys::remove(&content_path)
+                    .and_then(|()| sys::remove_dir(file.path()))
+            } else {
+                Ok(())
+            }
+        });
+        sys::close(desc);
+        file.set_desc(None);
+        result
+    }
+
+    fn remove_stale(&self, path: &OsStr) -> Result<bool, Error> {
+        // The directory is renamed first, so its removal, which is not atomic,
+        // does not race with new owners. A directory left aside by a crashed
+        // breaker would prevent renaming.
+        let stale_path = path.with_suffix(STALE_SUFFIX)?;
+        let _ = remove_lock_dir(&stale_path);
+        if !sys::rename_existing(path, &stale_path)? {
+            return Ok(false);
+        }
+        remove_lock_dir(&stale_path)?;
+        Ok(true)
+    }
+}
+
+/// Removes the lock directory at the given path along with its content, which
+/// may be missing if its owner crashed while locking.
+fn remove_lock_dir(path: &OsStr) -> Result<(), Error> {
+    let _ = sys::remove(&path.with_suffix(DIR_CONTENT_SUFFIX)?);
+    sys::remove_dir(path)
+}
+
+/// A backend not available on the current platform.
+#[derive(Debug)]
+struct UnavailableBackend;
+
+impl LockBackend for UnavailableBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn open(
+        &self,
+        _path: &OsStr,
+        _options: &LockFileOptions,
+    ) -> Result<Option<sys::FileDesc>, Error> {
+        Err(sys::unsupported())
+    }
+
+    fn lock(
+        &self,
+        _file: &mut LockTarget,
+        _mode: LockMode,
+    ) -> Result<(), Error> {
+        Err(sys::unsupported())
+    }
+
+    fn try_lock(
+        &self,
+        _file: &mut LockTarget,
+        _mode: LockMode,
+    ) -> Result<bool, Error> {
+        Err(sys::unsupported())
+    }
+
+    fn unlock(&self, _file: &mut LockTarget) -> Result<(), Error> {
+        Err(sys::unsupported())
+    }
+}
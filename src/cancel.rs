@@ -0,0 +1,181 @@
+//! This module implements lock acquisition which can be cancelled from another
+//! thread.
+
+use crate::{timeout::Backoff, Error, LockFile, LockMode};
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// State shared between the clones of a token.
+#[derive(Debug, Default)]
+struct Shared {
+    /// Whether the token was cancelled.
+    cancelled: Mutex<bool>,
+    /// Notified when the token is cancelled.
+    wake: Condvar,
+}
+
+/// A token cancelling [`LockFile::lock_cancellable`] and its variants from
+/// another thread. Clones share the same cancellation, so a clone may be kept
+/// by a waiting thread while another one is cancelled by a shutdown handler.
+/// Once cancelled, a token stays cancelled.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{CancelToken, Error, LockFile};
+/// use std::thread;
+///
+/// let path = "testfiles/cancel_token.lock";
+/// let mut holder = LockFile::open(path)?;
+/// holder.lock()?;
+///
+/// let token = CancelToken::new();
+/// let waiter = {
+///     let token = token.clone();
+///     thread::spawn(move || -> Result<(), Error> {
+///         let mut file = LockFile::open(path)?;
+///         file.lock_cancellable(&token)
+///     })
+/// };
+/// token.cancel();
+/// assert!(matches!(waiter.join().unwrap(), Err(Error::Cancelled)));
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    shared: Arc<Shared>,
+}
+
+impl CancelToken {
+    /// Creates a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels this token and its clones, waking every thread waiting for a
+    /// lock with it.
+    pub fn cancel(&self) {
+        *self.lock_cancelled() = true;
+        self.shared.wake.notify_all();
+    }
+
+    /// Returns whether this token or one of its clones was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.lock_cancelled()
+    }
+
+    /// Sleeps for the given duration, waking early if this token is
+    /// cancelled.
+    fn wait(&self, duration: Duration) {
+        let cancelled = self.lock_cancelled();
+        let _ = self.shared.wake.wait_timeout_while(
+            cancelled,
+            duration,
+            |cancelled| !*cancelled,
+        );
+    }
+
+    /// Locks the cancellation flag, ignoring poisoning, since a boolean is
+    /// always consistent.
+    fn lock_cancelled(&self) -> MutexGuard<'_, bool> {
+        self.shared
+            .cancelled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl LockFile {
+    /// Locks this file, blocking while it is not possible to lock (i.e.
+    /// someone else already owns a lock), until the given token is cancelled.
+    /// Fails with [`Error::Cancelled`] if the token is cancelled first,
+    /// including before this call. After locked, if no attempt to unlock is
+    /// made, it will be automatically unlocked on the file handle drop.
+    ///
+    /// Like [`LockFile::lock_timeout`], acquisition is done by polling
+    /// [`LockFile::try_lock`] with an exponential backoff, but the sleeps
+    /// between attempts end as soon as the token is cancelled. Thus no lock is
+    /// left behind: either this call succeeds and this handle owns the lock,
+    /// or it fails and it does not.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{CancelToken, LockFile};
+    ///
+    /// let token = CancelToken::new();
+    /// let mut file = LockFile::open("testfiles/cancellable.lock")?;
+    /// file.lock_cancellable(&token)?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_cancellable(
+        &mut self,
+        token: &CancelToken,
+    ) -> Result<(), Error> {
+        self.lock_cancellable_with_mode(LockMode::Exclusive, token)
+    }
+
+    /// Locks this file in shared mode, blocking while it is not possible to
+    /// lock (i.e. someone else owns an exclusive lock), until the given token
+    /// is cancelled. See [`LockFile::lock_cancellable`] for details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_shared_cancellable(
+        &mut self,
+        token: &CancelToken,
+    ) -> Result<(), Error> {
+        self.lock_cancellable_with_mode(LockMode::Shared, token)
+    }
+
+    /// Locks this file and writes this process's PID into the file, which will
+    /// be erased on unlock. Like [`LockFile::lock_cancellable`], blocks until
+    /// the given token is cancelled.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_with_pid_cancellable(
+        &mut self,
+        token: &CancelToken,
+    ) -> Result<(), Error> {
+        self.lock_cancellable(token)?;
+        self.write_pid()
+    }
+
+    /// Polls for a lock in the given mode until the given token is cancelled.
+    fn lock_cancellable_with_mode(
+        &mut self,
+        mode: LockMode,
+        token: &CancelToken,
+    ) -> Result<(), Error> {
+        self.check_unlocked()?;
+        let mut backoff = Backoff::new();
+        loop {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            if self.try_acquire(mode)? {
+                return Ok(());
+            }
+            if let Some(delay) = backoff.next_delay(None) {
+                token.wait(delay);
+            }
+        }
+    }
+}
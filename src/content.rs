@@ -0,0 +1,85 @@
+//! This module implements reading and writing the content of lock files.
+
+use crate::{sys, Error, LockFile};
+
+#[cfg(feature = "std")]
+use std::io;
+
+impl LockFile {
+    /// Reads the content of this file into the given buffer, starting at the
+    /// file's cursor and advancing it. Returns how many bytes were read, which
+    /// is less than the buffer's size only at the end of the file. Does not
+    /// require owning a lock, but the content may change meanwhile otherwise.
+    ///
+    /// Locking with a PID or owner information writes it at the start of the
+    /// file and leaves the cursor after it, and unlocking an exclusive lock
+    /// moves the cursor back to the start. Fails if no file is kept open, as
+    /// with [`Backend::Sentinel`](crate::Backend::Sentinel) while unlocked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockFileOptions};
+    ///
+    /// let path = "testfiles/read_into.lock";
+    /// let mut file =
+    ///     LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+    /// file.lock()?;
+    /// file.write_all(b"generation 1")?;
+    /// file.unlock()?;
+    ///
+    /// let mut other = LockFile::open(path)?;
+    /// other.lock_shared()?;
+    /// let mut buf = [0; 32];
+    /// let len = other.read_into(&mut buf)?;
+    /// assert_eq!(&buf[.. len], b"generation 1");
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        sys::read(self.open_desc()?, buf)
+    }
+
+    /// Writes all the given data into this file, starting at the file's cursor
+    /// and advancing it. See [`LockFile::read_into`] for how locking moves the
+    /// cursor. Unlocking an exclusive lock erases the content written, unless
+    /// disabled with [`LockFileOptions::truncate_on_unlock`].
+    ///
+    /// [`LockFileOptions::truncate_on_unlock`]:
+    /// crate::LockFileOptions::truncate_on_unlock
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        sys::write(self.open_desc()?, data)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Read for LockFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.read_into(buf)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for LockFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        LockFile::write_all(self, data)?;
+        Ok(data.len())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        Ok(LockFile::write_all(self, data)?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Seek for LockFile {
+    fn seek(&mut self, position: io::SeekFrom) -> io::Result<u64> {
+        Ok(sys::seek(self.open_desc()?, position)?)
+    }
+}
@@ -0,0 +1,236 @@
+//! This module implements locks held by creating a directory.
+
+use crate::{Backend, Error, LockFile, LockFileOptions, Pid, ToOsStr};
+
+#[cfg(feature = "std")]
+use crate::LockOwnerInfo;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A lock held by creating a directory, which is atomic even on old NFS
+/// versions, where advisory file locks may not work. Locking creates the
+/// directory and records the [owner information](crate#owner-information) in
+/// a file named `owner` inside it, and unlocking, explicitly or on drop,
+/// removes both. Only exclusive locks are supported, and blocking locks poll.
+/// This is a [`LockFile`] using [`Backend::Mkdir`].
+///
+/// Since the lock is not held by the operating system, a crashed owner leaves
+/// the directory behind, and nobody can lock until it is removed by hand. The
+/// recorded owner, read with [`LockDir::read_owner_pid`] or
+/// [`LockDir::is_owner_alive`], tells whether it is safe to remove.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockDir;
+///
+/// let mut dir = LockDir::open("testfiles/mydir.lock")?;
+/// dir.lock()?;
+/// do_stuff();
+/// dir.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LockDir {
+    file: LockFile,
+}
+
+impl LockDir {
+    /// Prepares to lock the directory at the given path. Does not create it.
+    ///
+    /// # Errors
+    /// Fails with [`Error::InvalidPath`] if the path contains a nul-byte in a
+    /// place other than the end.
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let file = LockFileOptions::new().backend(Backend::Mkdir).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Locks this directory, creating it. Blocks while it is not possible to
+    /// lock (i.e. the directory exists), polling for its removal. After
+    /// locked, if no attempt to unlock is made, it will be automatically
+    /// unlocked on the handle drop.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.file.lock()
+    }
+
+    /// Locks this directory, creating it, and writes this process's PID into
+    /// its owner file, replacing the owner information. Like
+    /// [`LockDir::lock`], blocks while it is not possible to lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_with_pid(&mut self) -> Result<(), Error> {
+        self.file.lock_with_pid()
+    }
+
+    /// Locks this directory, creating it, and records the owner information
+    /// with the given tag. Like [`LockDir::lock`], blocks while it is not
+    /// possible to lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_with_owner_info(
+        &mut self,
+        tag: Option<&str>,
+    ) -> Result<(), Error> {
+        self.file.lock_with_owner_info(tag)
+    }
+
+    /// Locks this directory, creating it. Does NOT block if it is not
+    /// possible to lock (i.e. the directory exists). Returns whether it was
+    /// locked.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockDir;
+    ///
+    /// let mut dir = LockDir::open("testfiles/attempt_dir.lock")?;
+    /// let mut other = LockDir::open("testfiles/attempt_dir.lock")?;
+    /// assert!(dir.try_lock()?);
+    /// assert!(!other.try_lock()?);
+    /// dir.unlock()?;
+    /// assert!(other.try_lock()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        self.file.try_lock()
+    }
+
+    /// Locks this directory and writes this process's PID into its owner
+    /// file, like [`LockDir::lock_with_pid`]. Does NOT block if it is not
+    /// possible to lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn try_lock_with_pid(&mut self) -> Result<bool, Error> {
+        self.file.try_lock_with_pid()
+    }
+
+    /// Locks this directory and records the owner information with the given
+    /// tag, like [`LockDir::lock_with_owner_info`]. Does NOT block if it is
+    /// not possible to lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn try_lock_with_owner_info(
+        &mut self,
+        tag: Option<&str>,
+    ) -> Result<bool, Error> {
+        self.file.try_lock_with_owner_info(tag)
+    }
+
+    /// Locks this directory, polling at most for the given duration while it
+    /// is not possible to lock. Returns whether the lock was acquired. See
+    /// [`LockFile::lock_timeout`].
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockDir;
+    /// use std::time::Duration;
+    ///
+    /// let mut dir = LockDir::open("testfiles/timeout_dir.lock")?;
+    /// if dir.lock_timeout(Duration::from_millis(100))? {
+    ///     do_stuff();
+    ///     dir.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn lock_timeout(&mut self, timeout: Duration) -> Result<bool, Error> {
+        self.file.lock_timeout(timeout)
+    }
+
+    /// Locks this directory, polling at most until the given deadline while
+    /// it is not possible to lock. Returns whether the lock was acquired. See
+    /// [`LockFile::lock_until`].
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    #[cfg(feature = "std")]
+    pub fn lock_until(&mut self, deadline: Instant) -> Result<bool, Error> {
+        self.file.lock_until(deadline)
+    }
+
+    /// Returns whether this handle owns the lock.
+    pub fn owns_lock(&self) -> bool {
+        self.file.owns_lock()
+    }
+
+    /// Unlocks this directory, removing it along with its owner file. This
+    /// handle must own the lock. If not called manually, it is automatically
+    /// called on `drop`.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NotLocked`] if this handle does not own the lock.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        self.file.unlock()
+    }
+
+    /// Reads the PID recorded in the owner file of this directory, possibly by
+    /// another handle or process. Returns `None` if the directory is not
+    /// locked. See [`LockFile::read_owner_pid`].
+    pub fn read_owner_pid(&self) -> Result<Option<Pid>, Error> {
+        self.file.read_owner_pid()
+    }
+
+    /// Returns whether the process recorded in the owner file of this
+    /// directory is still running. See [`LockFile::is_owner_alive`].
+    pub fn is_owner_alive(&self) -> Result<bool, Error> {
+        self.file.is_owner_alive()
+    }
+
+    /// Reads the owner information recorded in the owner file of this
+    /// directory. Returns `None` if the directory is not locked. See
+    /// [`LockFile::read_owner_info`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockDir;
+    ///
+    /// let mut dir = LockDir::open("testfiles/owner_dir.lock")?;
+    /// let other = LockDir::open("testfiles/owner_dir.lock")?;
+    /// dir.lock_with_owner_info(Some("nightly build"))?;
+    /// let info = other.read_owner_info()?.unwrap();
+    /// assert_eq!(info.tag.as_deref(), Some("nightly build"));
+    /// dir.unlock()?;
+    /// assert_eq!(other.read_owner_info()?, None);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_owner_info(&self) -> Result<Option<LockOwnerInfo>, Error> {
+        self.file.read_owner_info()
+    }
+}
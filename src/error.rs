@@ -0,0 +1,160 @@
+//! This module implements the error type of this crate.
+
+use crate::sys;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::io;
+
+/// An error locking a file or accessing it. Errors reported by the operating
+/// system are classified into the variants below when they have a meaning of
+/// their own, and are kept as [`Error::Os`] otherwise.
+///
+/// With the `std` feature, it converts from and into [`std::io::Error`], so
+/// `?` works both ways.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{Error, LockFile, LockFileOptions};
+///
+/// match LockFileOptions::new().create(false).open("testfiles/missing.lock") {
+///     Err(Error::NotSupported) => panic!("no locks here"),
+///     Err(error) => assert!(error.raw_os_error().is_some()),
+///     Ok(_) => panic!("the file does not exist"),
+/// }
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The operation would block, e.g. the file is locked by someone else.
+    WouldBlock,
+    /// The operation did not finish in time.
+    TimedOut,
+    /// The operation was interrupted, e.g. by a signal.
+    Interrupted,
+    /// Waiting for the lock would deadlock (`EDEADLK`), as detected by POSIX
+    /// record locks.
+    Deadlock,
+    /// The operation is not supported by the platform, the file system (e.g.
+    /// `flock` on some FUSE mounts) or the backend.
+    NotSupported,
+    /// The path contains a nul-byte in a place other than the end.
+    InvalidPath,
+    /// The handle already owns a lock.
+    AlreadyLocked,
+    /// The handle does not own a lock, or does not own it in the mode required
+    /// by the operation.
+    NotLocked,
+    /// Waiting for the lock was cancelled through a
+    /// [`CancelToken`](crate::CancelToken).
+    Cancelled,
+    /// Any other error reported by the operating system, with its raw code
+    /// (`errno` on Unix, `GetLastError` on Windows).
+    Os(i32),
+    /// An I/O error not coming from the operating system, such as one
+    /// returned by a custom [`LockBackend`](crate::LockBackend).
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+impl Error {
+    /// Creates an error from a raw OS error code, classifying it.
+    pub fn from_raw_os_error(code: i32) -> Self {
+        sys::classify_error(code)
+    }
+
+    /// Creates an error from the last OS error code of this thread.
+    pub fn last_os_error() -> Self {
+        Self::from_raw_os_error(sys::last_error_code())
+    }
+
+    /// Returns the raw OS error code of an [`Error::Os`], or of an
+    /// [`Error::Io`] coming from the operating system.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Self::Os(code) => Some(*code),
+            #[cfg(feature = "std")]
+            Self::Io(error) => error.raw_os_error(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WouldBlock => write!(fmt, "operation would block"),
+            Self::TimedOut => write!(fmt, "operation timed out"),
+            Self::Interrupted => write!(fmt, "operation interrupted"),
+            Self::Deadlock => write!(fmt, "deadlock detected"),
+            Self::NotSupported => write!(fmt, "operation not supported"),
+            Self::InvalidPath => {
+                write!(fmt, "path contains a nul-byte in the middle")
+            },
+            Self::AlreadyLocked => write!(fmt, "handle already owns a lock"),
+            Self::NotLocked => write!(fmt, "handle does not own a lock"),
+            Self::Cancelled => write!(fmt, "operation cancelled"),
+            #[cfg(feature = "std")]
+            Self::Os(code) => {
+                write!(fmt, "{}", io::Error::from_raw_os_error(*code))
+            },
+            #[cfg(not(feature = "std"))]
+            Self::Os(code) => sys::fmt_error(*code, fmt),
+            #[cfg(feature = "std")]
+            Self::Io(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        if let Some(code) = error.raw_os_error() {
+            return Self::from_raw_os_error(code);
+        }
+        match error.kind() {
+            io::ErrorKind::WouldBlock => Self::WouldBlock,
+            io::ErrorKind::TimedOut => Self::TimedOut,
+            io::ErrorKind::Interrupted => Self::Interrupted,
+            io::ErrorKind::Unsupported => Self::NotSupported,
+            // Unwraps an error converted by the implementation below.
+            _ if error.get_ref().is_some_and(|inner| inner.is::<Self>()) => {
+                let inner = error.into_inner().expect("checked above");
+                *inner.downcast::<Self>().expect("checked above")
+            },
+            _ => Self::Io(error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::WouldBlock => io::ErrorKind::WouldBlock,
+            Error::TimedOut => io::ErrorKind::TimedOut,
+            Error::Interrupted => io::ErrorKind::Interrupted,
+            Error::NotSupported => io::ErrorKind::Unsupported,
+            Error::InvalidPath => io::ErrorKind::InvalidInput,
+            Error::Os(code) => return io::Error::from_raw_os_error(code),
+            Error::Io(error) => return error,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error)
+    }
+}
@@ -1,14 +1,14 @@
 //! This module implements formatting functions for writing into lock files.
 
-use crate::sys;
+use crate::{sys, Error};
 use core::{
     fmt::{self, Write},
     mem,
 };
 
-/// I/O buffer size, chosen targeting possible PID's digits (I belive 11 would
-/// be enough tho).
-const BUF_SIZE: usize = 16;
+/// I/O buffer size, chosen targeting a few lines of owner information, although
+/// a PID's digits alone fit in 16 bytes.
+const BUF_SIZE: usize = 128;
 
 /// A fmt Writer that writes data into the given open file.
 #[derive(Debug, Clone, Copy)]
@@ -19,19 +19,50 @@ pub struct Writer(
 
 impl Writer {
     /// Writes formatting arguments into the file.
-    pub fn write_fmt(
-        &self,
-        arguments: fmt::Arguments,
-    ) -> Result<(), sys::Error> {
+    pub fn write_fmt(&self, arguments: fmt::Arguments) -> Result<(), Error> {
         let mut adapter = Adapter::new(self.0);
         let _ = adapter.write_fmt(arguments);
         adapter.finish()
     }
 }
 
+/// A fmt Writer that writes data into a fixed-size buffer, failing if it is
+/// too small.
+#[derive(Debug)]
+pub struct BufWriter<'buf> {
+    /// The buffer being written to.
+    buf: &'buf mut [u8],
+    /// How many bytes were written.
+    len: usize,
+}
+
+impl<'buf> BufWriter<'buf> {
+    /// Creates a writer at the start of the given buffer.
+    pub fn new(buf: &'buf mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Returns the bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[.. self.len]
+    }
+}
+
+impl Write for BufWriter<'_> {
+    fn write_str(&mut self, data: &str) -> fmt::Result {
+        let end = self.len + data.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len .. end].copy_from_slice(data.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
 /// Fmt <-> IO adapter.
 ///
-/// Buffer is flushed on drop.
+/// Buffer is flushed on finish.
 #[derive(Debug)]
 struct Adapter {
     /// File being written to.
@@ -41,7 +72,7 @@ struct Adapter {
     /// Cursor tracking where new bytes should be written at the buffer.
     cursor: usize,
     /// Partial result for writes.
-    result: Result<(), sys::Error>,
+    result: Result<(), Error>,
 }
 
 impl Adapter {
@@ -51,16 +82,18 @@ impl Adapter {
     }
 
     /// Flushes the buffer into the open file.
-    fn flush(&mut self) -> Result<(), sys::Error> {
+    fn flush(&mut self) -> Result<(), Error> {
         sys::write(self.desc, &self.buffer[.. self.cursor])?;
         self.buffer = [0; BUF_SIZE];
         self.cursor = 0;
         Ok(())
     }
 
-    /// Finishes the adapter, returning the I/O Result
-    fn finish(mut self) -> Result<(), sys::Error> {
-        mem::replace(&mut self.result, Ok(()))
+    /// Finishes the adapter, flushing the buffer and returning the I/O Result.
+    /// Does not sync the file to disk.
+    fn finish(mut self) -> Result<(), Error> {
+        mem::replace(&mut self.result, Ok(()))?;
+        self.flush()
     }
 }
 
@@ -88,10 +121,3 @@ impl Write for Adapter {
         }
     }
 }
-
-impl Drop for Adapter {
-    fn drop(&mut self) {
-        let _ = self.flush();
-        let _ = sys::fsync(self.desc);
-    }
-}
@@ -0,0 +1,304 @@
+//! This module implements runtime-agnostic futures for lock acquisition.
+
+use crate::{
+    timeout::{deadline_after, Backoff},
+    Error,
+    LockFile,
+    LockMode,
+    OwnedLockGuard,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Outcome of an acquisition: the locked file, or `None` on timeout.
+type Outcome = Result<Option<LockFile>, Error>;
+
+/// State shared between a future and its helper thread.
+#[derive(Debug)]
+struct Shared {
+    /// Deadline after which the helper thread gives up.
+    deadline: Option<Instant>,
+    /// Outcome delivered by the helper thread, not yet taken by the future.
+    outcome: Option<Outcome>,
+    /// Waker of the last task that polled the future.
+    waker: Option<Waker>,
+    /// Whether the future was dropped before completion.
+    cancelled: bool,
+}
+
+/// Locks the shared state, ignoring poisoning, since the state is always left
+/// consistent.
+fn lock_shared(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Acquisition state of a future.
+#[derive(Debug)]
+enum State {
+    /// Not polled yet.
+    Idle(LockFile),
+    /// A helper thread is waiting for the lock.
+    Waiting,
+    /// The output was already returned.
+    Done,
+}
+
+/// Runtime-agnostic acquisition shared by [`LockFuture`] and [`LockTimeout`].
+#[derive(Debug)]
+struct Acquire {
+    /// Mode of the lock being acquired.
+    mode: LockMode,
+    /// Acquisition state.
+    state: State,
+    /// State shared with the helper thread.
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Acquire {
+    /// Creates an acquisition of the given file, which fails if it already
+    /// owns a lock.
+    fn new(file: LockFile, mode: LockMode) -> Self {
+        Self {
+            mode,
+            state: State::Idle(file),
+            shared: Arc::new(Mutex::new(Shared {
+                deadline: None,
+                outcome: None,
+                waker: None,
+                cancelled: false,
+            })),
+        }
+    }
+
+    /// Polls the acquisition. The first poll tries to lock immediately, and
+    /// only spawns a helper thread if the file is already locked.
+    fn poll(&mut self, ctx: &mut Context) -> Poll<Outcome> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::Idle(mut file) => {
+                file.check_unlocked()?;
+                let deadline = lock_shared(&self.shared).deadline;
+                match file.try_acquire(self.mode) {
+                    Ok(true) => return Poll::Ready(Ok(Some(file))),
+                    Ok(false) => (),
+                    Err(error) => return Poll::Ready(Err(error)),
+                }
+                if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                    return Poll::Ready(Ok(None));
+                }
+                lock_shared(&self.shared).waker = Some(ctx.waker().clone());
+                let shared = self.shared.clone();
+                let mode = self.mode;
+                thread::Builder::new()
+                    .name("fslock-waiter".into())
+                    .spawn(move || wait(file, mode, &shared))?;
+                self.state = State::Waiting;
+                Poll::Pending
+            },
+
+            State::Waiting => {
+                let mut shared = lock_shared(&self.shared);
+                match shared.outcome.take() {
+                    Some(outcome) => Poll::Ready(outcome),
+                    None => {
+                        shared.waker = Some(ctx.waker().clone());
+                        drop(shared);
+                        self.state = State::Waiting;
+                        Poll::Pending
+                    },
+                }
+            },
+
+            State::Done => panic!("Lock future polled after completion"),
+        }
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        let mut shared = lock_shared(&self.shared);
+        shared.cancelled = true;
+        // Unlocks the file if the helper thread already acquired it.
+        let _ = shared.outcome.take();
+    }
+}
+
+/// Body of the helper thread: polls for the lock with backoff until acquired,
+/// timed out or cancelled.
+fn wait(mut file: LockFile, mode: LockMode, shared: &Mutex<Shared>) {
+    let mut backoff = Backoff::new();
+    let outcome = loop {
+        let deadline = {
+            let shared = lock_shared(shared);
+            if shared.cancelled {
+                return;
+            }
+            shared.deadline
+        };
+        match file.try_acquire(mode) {
+            Ok(true) => break Ok(Some(file)),
+            Ok(false) => (),
+            Err(error) => break Err(error),
+        }
+        if !backoff.sleep_until(deadline) {
+            break Ok(None);
+        }
+    };
+
+    let mut guard = lock_shared(shared);
+    if guard.cancelled {
+        // Dropping the outcome unlocks the file.
+        return;
+    }
+    guard.outcome = Some(outcome);
+    let waker = guard.waker.take();
+    drop(guard);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+/// A runtime-agnostic future which locks a [`LockFile`], resolving to an
+/// [`OwnedLockGuard`]. Created by [`LockFile::lock_future`] and
+/// [`LockFile::lock_shared_future`].
+///
+/// The first poll tries to lock immediately, like [`LockFile::try_lock`]. If
+/// the file is already locked, a dedicated helper thread waits for the lock,
+/// polling it with an exponential backoff, and wakes the task once done. No
+/// async runtime is required.
+///
+/// Dropping the future before completion cancels the acquisition: the helper
+/// thread stops, and the file is unlocked if it was acquired in the meantime.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct LockFuture {
+    /// The acquisition.
+    acquire: Acquire,
+}
+
+impl LockFuture {
+    /// Limits the acquisition to the given duration, counted from now. The
+    /// returned future resolves to `None` if the timeout expires before the
+    /// lock is acquired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// # fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #     let mut future = std::pin::pin!(future);
+    /// #     let mut ctx = std::task::Context::from_waker(std::task::Waker::noop());
+    /// #     loop {
+    /// #         if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+    /// #             break output;
+    /// #         }
+    /// #         std::thread::yield_now();
+    /// #     }
+    /// # }
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut holder = LockFile::open("testfiles/future_timeout.lock")?;
+    /// holder.lock()?;
+    ///
+    /// let file = LockFile::open("testfiles/future_timeout.lock")?;
+    /// let future = file.lock_future().timeout(Duration::from_millis(50));
+    /// assert!(block_on(future)?.is_none());
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn timeout(self, timeout: Duration) -> LockTimeout {
+        let deadline = deadline_after(timeout);
+        lock_shared(&self.acquire.shared).deadline = Some(deadline);
+        LockTimeout { acquire: self.acquire }
+    }
+}
+
+impl Future for LockFuture {
+    type Output = Result<OwnedLockGuard, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        self.acquire.poll(ctx).map(|outcome| {
+            let file = outcome?.expect("Timed out without a deadline");
+            Ok(OwnedLockGuard::new(file))
+        })
+    }
+}
+
+/// A runtime-agnostic future which locks a [`LockFile`] within a timeout,
+/// resolving to an [`OwnedLockGuard`], or to `None` if the timeout expires.
+/// Created by [`LockFuture::timeout`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct LockTimeout {
+    /// The acquisition.
+    acquire: Acquire,
+}
+
+impl Future for LockTimeout {
+    type Output = Result<Option<OwnedLockGuard>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        self.acquire
+            .poll(ctx)
+            .map(|outcome| Ok(outcome?.map(OwnedLockGuard::new)))
+    }
+}
+
+impl LockFile {
+    /// Returns a runtime-agnostic future which locks this file, taking
+    /// ownership of it and resolving to a guard which unlocks it when dropped.
+    /// See [`LockFuture`] for details.
+    ///
+    /// # Errors
+    /// The future fails with [`Error::AlreadyLocked`] if this handle already
+    /// owns the file, which is then dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// # fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #     let mut future = std::pin::pin!(future);
+    /// #     let mut ctx = std::task::Context::from_waker(std::task::Waker::noop());
+    /// #     loop {
+    /// #         if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+    /// #             break output;
+    /// #         }
+    /// #         std::thread::yield_now();
+    /// #     }
+    /// # }
+    /// use fslock::LockFile;
+    ///
+    /// let file = LockFile::open("testfiles/lock_future.lock")?;
+    /// let guard = block_on(file.lock_future())?;
+    /// do_stuff();
+    /// guard.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_future(self) -> LockFuture {
+        LockFuture { acquire: Acquire::new(self, LockMode::Exclusive) }
+    }
+
+    /// Returns a runtime-agnostic future which locks this file in shared mode,
+    /// taking ownership of it and resolving to a guard which unlocks it when
+    /// dropped. See [`LockFuture`] for details.
+    ///
+    /// # Errors
+    /// The future fails with [`Error::AlreadyLocked`] if this handle already
+    /// owns the file, which is then dropped.
+    pub fn lock_shared_future(self) -> LockFuture {
+        LockFuture { acquire: Acquire::new(self, LockMode::Shared) }
+    }
+}
@@ -0,0 +1,404 @@
+//! This module implements guards which unlock a file when dropped.
+
+use crate::{Error, LockFile};
+use core::ops::Deref;
+
+#[cfg(feature = "std")]
+use std::io;
+
+/// A guard over the lock owned by a [`LockFile`], borrowing it. The file is
+/// unlocked when the guard is dropped, so the critical section may freely
+/// return early, e.g. with `?`. Use [`LockGuard::unlock`] to observe errors
+/// while unlocking.
+///
+/// The guard dereferences to the underlying [`LockFile`], and reads and writes
+/// its content with [`LockGuard::read_into`] and [`LockGuard::write_all`], or
+/// with [`std::io::Read`], [`std::io::Write`] and [`std::io::Seek`].
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+///
+/// let mut file = LockFile::open("testfiles/guard.lock")?;
+/// {
+///     let guard = file.lock_guard()?;
+///     assert!(guard.owns_lock());
+///     do_stuff()?;
+/// }
+/// assert!(!file.owns_lock());
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() -> Result<(), fslock::Error> {
+/// #    // doing stuff here.
+/// #    Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LockGuard<'file> {
+    /// The locked file.
+    file: &'file mut LockFile,
+}
+
+impl<'file> LockGuard<'file> {
+    /// Wraps a file which already owns a lock.
+    pub(crate) fn new(file: &'file mut LockFile) -> Self {
+        Self { file }
+    }
+
+    /// Unlocks the file, consuming the guard and returning any error from
+    /// unlocking, which is otherwise silently ignored on drop.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/guard_unlock.lock")?;
+    /// let guard = file.lock_guard()?;
+    /// do_stuff();
+    /// guard.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn unlock(self) -> Result<(), Error> {
+        self.file.unlock()
+    }
+
+    /// Reads the content of the file into the given buffer. See
+    /// [`LockFile::read_into`].
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.file.read_into(buf)
+    }
+
+    /// Writes all the given data into the file. See [`LockFile::write_all`].
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockFileOptions};
+    ///
+    /// let path = "testfiles/guard_write_all.lock";
+    /// let mut file =
+    ///     LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+    /// file.lock_guard()?.write_all(b"state")?;
+    ///
+    /// let mut other = LockFile::open(path)?;
+    /// let mut buf = [0; 8];
+    /// let len = other.lock_shared_guard()?.read_into(&mut buf)?;
+    /// assert_eq!(&buf[.. len], b"state");
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.file.write_all(data)
+    }
+}
+
+impl Deref for LockGuard<'_> {
+    type Target = LockFile;
+
+    fn deref(&self) -> &LockFile {
+        self.file
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Read for LockGuard<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for LockGuard<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.file.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Seek for LockGuard<'_> {
+    fn seek(&mut self, position: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(position)
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if self.file.owns_lock() {
+            let _ = self.file.unlock();
+        }
+    }
+}
+
+/// A guard over the lock owned by a [`LockFile`], taking ownership of it.
+/// Unlike [`LockGuard`], it has no lifetime, so it can be stored in long-lived
+/// structs or moved across threads. The file is unlocked when the guard is
+/// dropped, and [`OwnedLockGuard::into_inner`] unlocks it and gives the
+/// [`LockFile`] back.
+///
+/// The guard dereferences to the underlying [`LockFile`], and reads and writes
+/// its content like [`LockGuard`].
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{LockFile, OwnedLockGuard};
+///
+/// struct AppState {
+///     lock: OwnedLockGuard,
+/// }
+///
+/// let file = LockFile::open("testfiles/owned_guard.lock")?;
+/// let state = AppState { lock: file.into_locked()? };
+/// assert!(state.lock.owns_lock());
+/// let file = state.lock.into_inner()?;
+/// assert!(!file.owns_lock());
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct OwnedLockGuard {
+    /// The locked file.
+    file: LockFile,
+}
+
+impl OwnedLockGuard {
+    /// Wraps a file which already owns a lock.
+    pub(crate) fn new(file: LockFile) -> Self {
+        Self { file }
+    }
+
+    /// Unlocks the file, consuming the guard and giving the file back.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let file = LockFile::open("testfiles/owned_guard_inner.lock")?;
+    /// let guard = file.into_locked()?;
+    /// do_stuff();
+    /// let mut file = guard.into_inner()?;
+    /// file.lock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn into_inner(mut self) -> Result<LockFile, Error> {
+        self.file.unlock()?;
+        Ok(self.file)
+    }
+
+    /// Unlocks the file, consuming the guard and returning any error from
+    /// unlocking, which is otherwise silently ignored on drop. The file is
+    /// closed.
+    pub fn unlock(self) -> Result<(), Error> {
+        self.into_inner().map(drop)
+    }
+
+    /// Reads the content of the file into the given buffer. See
+    /// [`LockFile::read_into`].
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.file.read_into(buf)
+    }
+
+    /// Writes all the given data into the file. See [`LockFile::write_all`].
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.file.write_all(data)
+    }
+}
+
+impl Deref for OwnedLockGuard {
+    type Target = LockFile;
+
+    fn deref(&self) -> &LockFile {
+        &self.file
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Read for OwnedLockGuard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for OwnedLockGuard {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.file.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Seek for OwnedLockGuard {
+    fn seek(&mut self, position: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(position)
+    }
+}
+
+impl LockFile {
+    /// Locks this file like [`LockFile::lock`], taking ownership of it and
+    /// returning a guard which unlocks it when dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn into_locked(mut self) -> Result<OwnedLockGuard, Error> {
+        self.lock()?;
+        Ok(OwnedLockGuard::new(self))
+    }
+
+    /// Locks this file in shared mode like [`LockFile::lock_shared`], taking
+    /// ownership of it and returning a guard which unlocks it when dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn into_locked_shared(mut self) -> Result<OwnedLockGuard, Error> {
+        self.lock_shared()?;
+        Ok(OwnedLockGuard::new(self))
+    }
+
+    /// Locks this file and writes this process's PID into it like
+    /// [`LockFile::lock_with_pid`], taking ownership of it and returning a
+    /// guard which unlocks it when dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn into_locked_with_pid(mut self) -> Result<OwnedLockGuard, Error> {
+        self.lock_with_pid()?;
+        Ok(OwnedLockGuard::new(self))
+    }
+
+    /// Locks this file like [`LockFile::lock`], returning a guard which unlocks
+    /// it when dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_guard.lock")?;
+    /// let guard = file.lock_guard()?;
+    /// do_stuff();
+    /// drop(guard);
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_guard(&mut self) -> Result<LockGuard<'_>, Error> {
+        self.lock()?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Locks this file in shared mode like [`LockFile::lock_shared`], returning
+    /// a guard which unlocks it when dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_shared_guard(&mut self) -> Result<LockGuard<'_>, Error> {
+        self.lock_shared()?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Locks this file and writes this process's PID into it like
+    /// [`LockFile::lock_with_pid`], returning a guard which unlocks it when
+    /// dropped.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_with_pid_guard(&mut self) -> Result<LockGuard<'_>, Error> {
+        self.lock_with_pid()?;
+        Ok(LockGuard::new(self))
+    }
+
+    /// Tries to lock this file like [`LockFile::try_lock`], returning a guard
+    /// which unlocks it when dropped, or `None` if someone else already owns a
+    /// lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/try_lock_guard.lock")?;
+    /// if let Some(_guard) = file.try_lock_guard()? {
+    ///     do_stuff();
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn try_lock_guard(&mut self) -> Result<Option<LockGuard<'_>>, Error> {
+        if self.try_lock()? {
+            Ok(Some(LockGuard::new(self)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Tries to lock this file in shared mode like
+    /// [`LockFile::try_lock_shared`], returning a guard which unlocks it when
+    /// dropped, or `None` if someone else owns an exclusive lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn try_lock_shared_guard(
+        &mut self,
+    ) -> Result<Option<LockGuard<'_>>, Error> {
+        if self.try_lock_shared()? {
+            Ok(Some(LockGuard::new(self)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Tries to lock this file and write this process's PID into it like
+    /// [`LockFile::try_lock_with_pid`], returning a guard which unlocks it when
+    /// dropped, or `None` if someone else already owns a lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn try_lock_with_pid_guard(
+        &mut self,
+    ) -> Result<Option<LockGuard<'_>>, Error> {
+        if self.try_lock_with_pid()? {
+            Ok(Some(LockGuard::new(self)))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! This module implements the policy for lock operations interrupted by
+//! signals.
+
+use crate::Error;
+use core::{
+    hash::{Hash, Hasher},
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// What to do when locking, unlocking or converting a lock is interrupted by
+/// a signal (`EINTR` on Unix), configured with
+/// [`LockFileOptions::interrupt_policy`]. Applies to blocking and non-blocking
+/// calls alike, so a signal never turns into a spurious "would block".
+///
+/// Signals only interrupt calls when their handler is installed without
+/// `SA_RESTART`, otherwise the system restarts the call by itself.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use core::sync::atomic::AtomicBool;
+/// use fslock::{InterruptPolicy, LockFileOptions};
+///
+/// // Set by a signal handler to stop waiting.
+/// static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+///
+/// let mut file = LockFileOptions::new()
+///     .interrupt_policy(InterruptPolicy::Flag(&SHUTDOWN))
+///     .open("testfiles/interrupt_policy.lock")?;
+/// file.lock()?;
+///
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`LockFileOptions::interrupt_policy`]:
+/// crate::LockFileOptions::interrupt_policy
+#[derive(Debug, Clone, Copy, Default)]
+pub enum InterruptPolicy {
+    /// Retries the operation transparently. The default.
+    #[default]
+    Retry,
+    /// Fails with [`Error::Interrupted`].
+    Fail,
+    /// Retries the operation while the flag is unset, and fails with
+    /// [`Error::Interrupted`] once it is set, e.g. by a signal handler. The
+    /// flag is only checked after an interruption.
+    Flag(&'static AtomicBool),
+}
+
+impl InterruptPolicy {
+    /// Runs the given operation, running it again while it is interrupted and
+    /// this policy says to retry.
+    pub(crate) fn run<F, T>(self, mut operation: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Result<T, Error>,
+    {
+        loop {
+            match operation() {
+                Err(Error::Interrupted) if self.retries() => (),
+                result => break result,
+            }
+        }
+    }
+
+    /// Whether an interrupted operation should be retried now.
+    fn retries(self) -> bool {
+        match self {
+            Self::Retry => true,
+            Self::Fail => false,
+            Self::Flag(flag) => !flag.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl PartialEq for InterruptPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Retry, Self::Retry) | (Self::Fail, Self::Fail) => true,
+            (Self::Flag(flag), Self::Flag(other)) => ptr::eq(*flag, *other),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for InterruptPolicy {}
+
+impl Hash for InterruptPolicy {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        match self {
+            Self::Retry => 0u8.hash(state),
+            Self::Fail => 1u8.hash(state),
+            Self::Flag(flag) => {
+                2u8.hash(state);
+                ptr::hash(*flag, state);
+            },
+        }
+    }
+}
@@ -6,10 +6,115 @@
 //! `std`.
 //!
 //! # Types
-//! Currently, only one type is provided: [`LockFile`]. It does not destroy the
-//! file after closed. Locks are per-handle and not by per-process in any
-//! platform. On Unix, however, under `fork` file descriptors might be
-//! duplicated sharing the same lock, but `fork` is usually `unsafe` in Rust.
+//! The main type is [`LockFile`], along with the guards [`LockGuard`] and
+//! [`OwnedLockGuard`], which unlock it when dropped. By default, it does not
+//! delete the file when unlocked or closed, unless the lock is the file
+//! itself, as with [`Backend::Sentinel`] and [`Backend::Mkdir`]. Deleting the
+//! file on unlock is opt-in through [`LockFileOptions::delete_on_unlock`], in
+//! which case handles acquiring a lock check that the file was not deleted
+//! meanwhile (see [Deleting on Unlock](#deleting-on-unlock)). Locks are
+//! per-handle and not by per-process in any platform. On Unix, however, under
+//! `fork` file descriptors might be duplicated sharing the same lock, but
+//! `fork` is usually `unsafe` in Rust.
+//!
+//! The mechanism used to lock is a [`Backend`], chosen with
+//! [`LockFile::open_with`] and [`LockFileOptions::backend`]: `flock` on Unix
+//! and `LockFileEx` on Windows by default, open file description locks on Linux
+//! and Android, classic POSIX record locks on Unix, sentinel files created with
+//! `O_EXCL`, lock directories, or a custom [`LockBackend`].
+//! [`Backend::capabilities`] tells which features each of them supports.
+//! [`LockDir`] is a lock held by creating a directory, for network file
+//! systems.
+//!
+//! Failures are reported as an [`Error`], which classifies errors from the
+//! operating system, and converts from and into `std::io::Error`.
+//!
+//! # Owner Information
+//!
+//! [`LockFile::lock_with_pid`] writes only the PID followed by a newline.
+//! [`LockFile::lock_with_owner_info`] writes one `key=value` entry per line,
+//! always starting with `pid`, followed by `start_time` if available:
+//!
+//! ```text
+//! pid=1234
+//! start_time=5813392
+//! hostname=build-01
+//! exe=/usr/bin/builder
+//! acquired_at=1700000000
+//! tag=nightly
+//! ```
+//!
+//! - `pid`: ID of the owner process.
+//! - `start_time`: start time of the owner process, in clock ticks since boot,
+//!   from field 22 of `/proc/<pid>/stat`. Linux and Android only. Together with
+//!   `pid`, it identifies the owner even if its PID is later reused.
+//! - `hostname`: name of the host the owner runs on.
+//! - `exe`: path of the owner's executable.
+//! - `acquired_at`: when the lock was acquired, in seconds since the Unix
+//!   epoch.
+//! - `tag`: free-form text supplied by the owner.
+//!
+//! Every entry but `pid` is omitted when not available. Values are UTF-8 and
+//! never contain line breaks: invalid sequences and line breaks are replaced
+//! by `U+FFFD`. Readers must ignore unknown keys, so entries may be added in
+//! the future.
+//!
+//! # Byte-Range Locks
+//!
+//! [`LockFile::lock_range`], [`LockFile::try_lock_range`] and
+//! [`LockFile::unlock_range`] lock regions of a file, given by an offset and a
+//! length in bytes. A length of zero extends the region indefinitely, past the
+//! end of the file. Shared regions may overlap each other, but an exclusive
+//! region excludes any other overlapping region.
+//!
+//! Range locks are independent from whole-file locks such as
+//! [`LockFile::lock`], and are not reported by [`LockFile::owns_lock`]. On
+//! Linux and Android, they are open file description locks (`F_OFD_SETLK`),
+//! held per handle. On other Unix systems, they are classic POSIX record locks
+//! (`F_SETLK`), held per process: handles in the same process do not exclude
+//! each other, and closing any handle to the file releases every range lock
+//! the process holds on it. On Windows, they are `LockFileEx` locks, held per
+//! handle, and whole-file locks are implemented as a lock over the last bytes
+//! of the 64-bit range, which regions extending indefinitely stop short of.
+//! Dropping the handle releases its range locks.
+//!
+//! Regions locked by the same handle behave differently across platforms:
+//!
+//! - On Unix, a handle holds at most one lock mode over each byte. Locking a
+//!   region overlapping a region already held by the same handle replaces the
+//!   mode over the overlap, atomically converting it, and adjacent or
+//!   overlapping regions in the same mode merge. Unlocking any part of a region
+//!   splits it, keeping the rest locked, and unlocking a region which is not
+//!   locked does nothing.
+//! - On Windows, regions never merge nor split, and must be unlocked with the
+//!   same offset and length they were locked with. A handle may lock a region
+//!   in shared mode more than once, or over its own exclusive region, but an
+//!   exclusive lock overlapping any region held by the same handle never
+//!   succeeds.
+//!
+//! Portable code should therefore lock disjoint regions and unlock exactly the
+//! regions it has locked.
+//!
+//! # Deleting on Unlock
+//!
+//! By default, lock files are never deleted. With
+//! [`LockFileOptions::delete_on_unlock`], a handle unlocking an exclusive lock
+//! deletes the file while still holding the lock, and a handle unlocking a
+//! shared lock deletes it if no other handle holds a lock on it. Since a
+//! handle may open the file, or wait for its lock, right before it is deleted,
+//! every handle acquiring a lock then checks that the file at the lock path is
+//! still the one it locked, comparing device and inode numbers, and opens the
+//! new file and tries again otherwise. For this guarantee, every handle using
+//! the file must be opened with this option. Deleting on unlock is supported
+//! on Unix only, where a file may be deleted while other handles have it open.
+//!
+//! # File Content
+//!
+//! Besides owner information, small state may be stored in the lock file
+//! itself through [`LockFile::read_into`] and [`LockFile::write_all`], or
+//! `std::io::Read`, `Write` and `Seek`, also implemented by the guards.
+//! Unlocking an exclusive lock erases the content unless disabled with
+//! [`LockFileOptions::truncate_on_unlock`].
 //!
 //! # Example
 //! ```
@@ -37,7 +142,31 @@ mod unix;
 use crate::unix as sys;
 
 mod string;
+mod backend;
+mod content;
+mod dir;
+mod error;
 mod fmt;
+mod guard;
+mod interrupt;
+mod options;
+mod owner;
+mod range;
+
+#[cfg(feature = "std")]
+mod cancel;
+
+#[cfg(feature = "std")]
+mod timeout;
+
+#[cfg(feature = "std")]
+mod stale;
+
+#[cfg(feature = "tokio")]
+mod async_tokio;
+
+#[cfg(feature = "async")]
+mod future;
 
 #[cfg(windows)]
 mod windows;
@@ -45,14 +174,32 @@ mod windows;
 use crate::windows as sys;
 
 pub use crate::{
+    backend::{Backend, Capabilities, LockBackend, LockTarget},
+    dir::LockDir,
+    error::Error,
+    guard::{LockGuard, OwnedLockGuard},
+    interrupt::InterruptPolicy,
+    options::LockFileOptions,
+    owner::{is_pid_alive, owner_pid},
     string::{EitherOsStr, IntoOsString, ToOsStr},
-    sys::{Error, OsStr, OsString},
+    sys::{FileDesc, OsStr, OsString, Pid},
+};
+
+#[cfg(feature = "async")]
+pub use crate::future::{LockFuture, LockTimeout};
+
+#[cfg(feature = "std")]
+pub use crate::{
+    cancel::CancelToken,
+    owner::{owner_info, LockOwnerInfo},
+    stale::StalePolicy,
 };
 
 #[derive(Debug)]
-/// A handle to a file that is lockable. Does not delete the file. On both
-/// Unix and Windows, the lock is held by an individual handle, and not by the
-/// whole process. On Unix, however, under `fork` file descriptors might be
+/// A handle to a file that is lockable. Does not delete the file, unless
+/// [deleting on unlock](crate#deleting-on-unlock) is enabled. On both Unix and
+/// Windows, the lock is held by an individual handle, and not by the whole
+/// process. On Unix, however, under `fork` file descriptors might be
 /// duplicated sharing the same lock, but `fork` is usually `unsafe` in Rust.
 ///
 /// # Example
@@ -71,9 +218,31 @@ pub use crate::{
 /// #    // doing stuff here.
 /// # }
 /// ```
+///
+/// # File descriptors and handles
+///
+/// The file kept open is exposed through `AsRawFd` and `AsFd` on Unix, and
+/// `AsRawHandle` and `AsHandle` on Windows. Backends which keep no file open
+/// while unlocked, such as [`Backend::Sentinel`] and [`Backend::Mkdir`], have
+/// none to expose then, and those implementations panic. `try_as_fd` on Unix
+/// and `try_as_handle` on Windows fail with an error instead.
 pub struct LockFile {
-    locked: bool,
-    desc: sys::FileDesc,
+    mode: Option<LockMode>,
+    desc: Option<sys::FileDesc>,
+    options: LockFileOptions,
+    /// `None` if the file was not opened by path.
+    path: Option<sys::OsString>,
+}
+
+/// The mode in which a lock is held by a [`LockFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    /// A shared (reader) lock. Any number of handles may hold a shared lock at
+    /// the same time, as long as no handle holds an exclusive lock.
+    Shared,
+    /// An exclusive (writer) lock. While a handle holds it, no other handle
+    /// can hold any lock on the file.
+    Exclusive,
 }
 
 impl LockFile {
@@ -89,47 +258,245 @@ impl LockFile {
     /// under `fork` file descriptors might be duplicated sharing the same lock,
     /// but `fork` is usually `unsafe` in Rust.
     ///
-    /// # Panics
-    /// Panics if the path contains a nul-byte in a place other than the end.
+    /// # Errors
+    /// Fails with [`Error::InvalidPath`] if the path contains a nul-byte in a
+    /// place other than the end.
     ///
     /// # Example
     ///
     /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Error, LockFile};
     ///
     /// let mut file = LockFile::open("testfiles/regular.lock")?;
+    /// assert!(matches!(LockFile::open("my\0lock"), Err(Error::InvalidPath)));
     ///
     /// # Ok(())
     /// # }
     /// ```
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        LockFileOptions::new().open(path)
+    }
+
+    /// Opens a file for locking, with the given options, such as the
+    /// [`Backend`] used to lock it. Same as [`LockFileOptions::open`].
     ///
-    /// # Panicking Example
+    /// # Errors
+    /// Fails with [`Error::InvalidPath`] if the path contains a nul-byte in a
+    /// place other than the end.
+    ///
+    /// # Example
     ///
-    /// ```should_panic
+    /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Backend, LockFile, LockFileOptions};
     ///
-    /// let mut file = LockFile::open("my\0lock")?;
+    /// let backend = if Backend::Ofd.is_available() {
+    ///     Backend::Ofd
+    /// } else {
+    ///     Backend::Native
+    /// };
+    /// let mut file = LockFile::open_with(
+    ///     "testfiles/open_with.lock",
+    ///     LockFileOptions::new().backend(backend),
+    /// )?;
+    /// file.lock()?;
+    /// assert_eq!(file.backend(), backend);
     ///
     /// # Ok(())
     /// # }
     /// ```
-    pub fn open<P>(path: &P) -> Result<Self, Error>
+    pub fn open_with<P>(
+        path: &P,
+        options: &LockFileOptions,
+    ) -> Result<Self, Error>
     where
         P: ToOsStr + ?Sized,
     {
-        let path = path.to_os_str()?;
-        let desc = sys::open(path.as_ref())?;
-        Ok(Self { locked: false, desc })
+        options.open(path)
+    }
+
+    /// Wraps a file opened elsewhere, such as the data file itself, in order to
+    /// lock it with [`Backend::Native`]. Since it may hold data, its content is
+    /// not truncated when unlocking. Breaking stale locks is not supported, as
+    /// the path of the file is unknown. See [`LockFile::into_file`] for the
+    /// reverse conversion.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::fs::File;
+    ///
+    /// let data = File::create("testfiles/from_file.lock")?;
+    /// let mut file = LockFile::from_file(data);
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    /// assert!(file.into_file()?.metadata()?.is_file());
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_file(file: std::fs::File) -> Self {
+        #[cfg(unix)]
+        let desc = std::os::fd::IntoRawFd::into_raw_fd(file);
+        #[cfg(windows)]
+        let desc = std::os::windows::io::IntoRawHandle::into_raw_handle(file)
+            as sys::FileDesc;
+        Self::from_desc(desc)
+    }
+
+    /// Unwraps the file kept open by this handle, which must not own a lock.
+    /// See [`LockFile::from_file`]. On failure, the handle is dropped as usual,
+    /// releasing its lock.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle owns a lock, and
+    /// with an OS error if its backend keeps no file open, such as
+    /// [`Backend::Sentinel`].
+    #[cfg(feature = "std")]
+    pub fn into_file(mut self) -> Result<std::fs::File, Error> {
+        self.check_unlocked()?;
+        let desc = self.open_desc()?;
+        self.desc = None;
+        #[cfg(unix)]
+        unsafe {
+            Ok(std::os::fd::FromRawFd::from_raw_fd(desc))
+        }
+        #[cfg(windows)]
+        unsafe {
+            Ok(std::os::windows::io::FromRawHandle::from_raw_handle(desc as _))
+        }
+    }
+
+    /// Takes ownership of an open file, without a path.
+    #[cfg(feature = "std")]
+    fn from_desc(desc: sys::FileDesc) -> Self {
+        let options =
+            LockFileOptions { truncate_on_unlock: false, ..Default::default() };
+        Self { mode: None, desc: Some(desc), options, path: None }
+    }
+
+    /// Releases the lock owned by this handle, if any, ignoring errors like on
+    /// drop, and then ownership of the file kept open by it, if any. If
+    /// unlocking fails, the file is handed over still locked.
+    #[cfg(feature = "std")]
+    fn into_desc(mut self) -> Option<sys::FileDesc> {
+        if self.mode.is_some() {
+            let _ = self.unlock();
+        }
+        self.mode = None;
+        self.desc.take()
+    }
+
+    /// Fails with [`Error::AlreadyLocked`] if this handle owns a lock.
+    pub(crate) fn check_unlocked(&self) -> Result<(), Error> {
+        if self.mode.is_some() {
+            Err(Error::AlreadyLocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the backend used to lock this file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Backend, LockFile};
+    ///
+    /// let file = LockFile::open("testfiles/backend.lock")?;
+    /// assert_eq!(file.backend(), Backend::Native);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn backend(&self) -> Backend {
+        self.options.backend
+    }
+
+    /// Returns the implementation of this file's backend, along with the
+    /// target it operates on.
+    pub(crate) fn backend_target(
+        &mut self,
+    ) -> (&'static dyn LockBackend, LockTarget<'_>) {
+        let path = match &self.path {
+            Some(path) => path.as_ref(),
+            None => OsStr::empty(),
+        };
+        let target = LockTarget::new(path, &self.options, &mut self.desc);
+        (self.options.backend.implementation(), target)
+    }
+
+    /// Returns the path of the lock file, or an empty path if it was not
+    /// opened by path, on which every operation fails.
+    pub(crate) fn lock_path(&self) -> &OsStr {
+        match &self.path {
+            Some(path) => path.as_ref(),
+            None => OsStr::empty(),
+        }
+    }
+
+    /// Returns the file kept open by this handle, failing if there is none.
+    pub(crate) fn open_desc(&self) -> Result<sys::FileDesc, Error> {
+        self.desc.ok_or_else(sys::not_open)
+    }
+
+    /// Replaces this handle's file with the one currently at the lock path,
+    /// creating it if needed. Must not own a lock.
+    pub(crate) fn reopen(&mut self) -> Result<(), Error> {
+        let backend = self.options.backend.implementation();
+        let desc = backend.open(self.lock_path(), &self.options)?;
+        self.close();
+        self.desc = desc;
+        Ok(())
+    }
+
+    /// Closes the file kept open by this handle. Must not own a lock.
+    fn close(&mut self) {
+        let (backend, mut target) = self.backend_target();
+        backend.close(&mut target);
+    }
+
+    /// Checks whether the lock just acquired by this handle's backend is on
+    /// the file currently at the lock path, which another handle may have
+    /// deleted on unlock meanwhile. Otherwise, releases the lock and opens the
+    /// file currently at the path. Returns whether the lock is valid. Only
+    /// checks if the file is deleted on unlock.
+    fn check_acquired(&mut self) -> Result<bool, Error> {
+        let desc = match self.desc {
+            Some(desc) if self.options.delete_on_unlock => desc,
+            _ => return Ok(true),
+        };
+        let same_file = sys::same_file(self.lock_path(), desc);
+        if let Ok(true) = same_file {
+            return Ok(true);
+        }
+        let (backend, mut target) = self.backend_target();
+        let unlock_result =
+            target.interruptible(|target| backend.unlock(target));
+        same_file?;
+        unlock_result?;
+        self.reopen()?;
+        Ok(false)
     }
 
     /// Locks this file. Blocks while it is not possible to lock (i.e. someone
     /// else already owns a lock). After locked, if no attempt to unlock is
     /// made, it will be automatically unlocked on the file handle drop.
     ///
-    /// # Panics
-    /// Panics if this handle already owns the file.
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
     ///
     /// # Example
     ///
@@ -149,25 +516,80 @@ impl LockFile {
     /// # }
     /// ```
     ///
-    /// # Panicking Example
+    /// # Misuse Example
     ///
-    /// ```should_panic
+    /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Error, LockFile};
     ///
-    /// let mut file = LockFile::open("testfiles/panicking.lock")?;
-    /// file.lock()?;
+    /// let mut file = LockFile::open("testfiles/misuse.lock")?;
     /// file.lock()?;
+    /// assert!(matches!(file.lock(), Err(Error::AlreadyLocked)));
     ///
     /// # Ok(())
     /// # }
     /// ```
     pub fn lock(&mut self) -> Result<(), Error> {
-        if self.locked {
-            panic!("Cannot lock if already owning a lock");
+        self.lock_with_mode(LockMode::Exclusive)
+    }
+
+    /// Locks this file in shared mode. Blocks while it is not possible to lock
+    /// (i.e. someone else owns an exclusive lock). Other handles may hold a
+    /// shared lock at the same time. After locked, if no attempt to unlock is
+    /// made, it will be automatically unlocked on the file handle drop.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut reader_a = LockFile::open("testfiles/shared.lock")?;
+    /// let mut reader_b = LockFile::open("testfiles/shared.lock")?;
+    /// reader_a.lock_shared()?;
+    /// reader_b.lock_shared()?;
+    /// do_stuff();
+    /// reader_a.unlock()?;
+    /// reader_b.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Misuse Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Error, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/shared_misuse.lock")?;
+    /// file.lock_shared()?;
+    /// assert!(matches!(file.lock_shared(), Err(Error::AlreadyLocked)));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_shared(&mut self) -> Result<(), Error> {
+        self.lock_with_mode(LockMode::Shared)
+    }
+
+    /// Locks this file in the given mode, blocking.
+    fn lock_with_mode(&mut self, mode: LockMode) -> Result<(), Error> {
+        self.check_unlocked()?;
+        loop {
+            let (backend, mut target) = self.backend_target();
+            target.interruptible(|target| backend.lock(target, mode))?;
+            if self.check_acquired()? {
+                break;
+            }
         }
-        sys::lock(self.desc)?;
-        self.locked = true;
+        self.mode = Some(mode);
         Ok(())
     }
 
@@ -176,8 +598,8 @@ impl LockFile {
     /// possible to lock. After locked, if no attempt to unlock is made, it will
     /// be automatically unlocked on the file handle drop.
     ///
-    /// # Panics
-    /// Panics if this handle already owns the file.
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
     ///
     /// # Example
     ///
@@ -208,12 +630,7 @@ impl LockFile {
     /// ```
     pub fn lock_with_pid(&mut self) -> Result<(), Error> {
         self.lock()?;
-
-        let result = writeln!(fmt::Writer(self.desc), "{}", sys::pid());
-        if result.is_err() {
-            let _ = self.unlock();
-        }
-        result
+        self.write_pid()
     }
 
     /// Locks this file. Does NOT block if it is not possible to lock (i.e.
@@ -221,8 +638,8 @@ impl LockFile {
     /// unlock is made, it will be automatically unlocked on the file handle
     /// drop.
     ///
-    /// # Panics
-    /// Panics if this handle already owns the file.
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
     ///
     /// # Example
     ///
@@ -243,28 +660,88 @@ impl LockFile {
     /// # }
     /// ```
     ///
-    /// # Panicking Example
+    /// # Misuse Example
     ///
-    /// ```should_panic
+    /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Error, LockFile};
     ///
-    /// let mut file = LockFile::open("testfiles/attempt_panic.lock")?;
+    /// let mut file = LockFile::open("testfiles/attempt_misuse.lock")?;
     /// file.lock()?;
-    /// file.try_lock()?;
+    /// assert!(matches!(file.try_lock(), Err(Error::AlreadyLocked)));
     ///
     /// # Ok(())
     /// # }
     /// ```
     pub fn try_lock(&mut self) -> Result<bool, Error> {
-        if self.locked {
-            panic!("Cannot lock if already owning a lock");
-        }
-        let lock_result = sys::try_lock(self.desc);
-        if let Ok(true) = lock_result {
-            self.locked = true;
+        self.try_lock_with_mode(LockMode::Exclusive)
+    }
+
+    /// Locks this file in shared mode. Does NOT block if it is not possible to
+    /// lock (i.e. someone else owns an exclusive lock). After locked, if no
+    /// attempt to unlock is made, it will be automatically unlocked on the file
+    /// handle drop.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut writer = LockFile::open("testfiles/shared_attempt.lock")?;
+    /// let mut reader = LockFile::open("testfiles/shared_attempt.lock")?;
+    /// writer.lock()?;
+    /// assert!(!reader.try_lock_shared()?);
+    /// writer.unlock()?;
+    /// assert!(reader.try_lock_shared()?);
+    /// reader.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Misuse Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Error, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/shared_attempt_misuse.lock")?;
+    /// file.lock()?;
+    /// assert!(matches!(file.try_lock_shared(), Err(Error::AlreadyLocked)));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock_shared(&mut self) -> Result<bool, Error> {
+        self.try_lock_with_mode(LockMode::Shared)
+    }
+
+    /// Locks this file in the given mode, without blocking.
+    fn try_lock_with_mode(&mut self, mode: LockMode) -> Result<bool, Error> {
+        self.check_unlocked()?;
+        self.try_acquire(mode)
+    }
+
+    /// Attempts to lock this file in the given mode without blocking,
+    /// recording the lock if acquired. Must not own a lock.
+    pub(crate) fn try_acquire(
+        &mut self,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        loop {
+            let (backend, mut target) = self.backend_target();
+            if !target.interruptible(|target| backend.try_lock(target, mode))? {
+                return Ok(false);
+            }
+            if self.check_acquired()? {
+                self.mode = Some(mode);
+                return Ok(true);
+            }
         }
-        lock_result
     }
 
     /// Locks this file and writes this process's PID into the file, which will
@@ -273,8 +750,8 @@ impl LockFile {
     /// unlock is made, it will be automatically unlocked on the file handle
     /// drop.
     ///
-    /// # Panics
-    /// Panics if this handle already owns the file.
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
     ///
     /// # Example
     ///
@@ -304,15 +781,15 @@ impl LockFile {
     /// }
     /// ```
     ///
-    /// # Panicking Example
+    /// # Misuse Example
     ///
-    /// ```should_panic
+    /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Error, LockFile};
     ///
-    /// let mut file = LockFile::open("testfiles/pid_attempt_panic.lock")?;
+    /// let mut file = LockFile::open("testfiles/pid_attempt_misuse.lock")?;
     /// file.lock_with_pid()?;
-    /// file.try_lock_with_pid()?;
+    /// assert!(matches!(file.try_lock_with_pid(), Err(Error::AlreadyLocked)));
     ///
     /// # Ok(())
     /// # }
@@ -324,12 +801,240 @@ impl LockFile {
             Err(error) => return Err(error),
         }
 
-        let result = sys::truncate(self.desc)
-            .and_then(|_| writeln!(fmt::Writer(self.desc), "{}", sys::pid()));
+        self.write_pid().map(|_| true)
+    }
+
+    /// Writes this process's PID into the locked file, replacing any previous
+    /// content. Unlocks the file on error.
+    fn write_pid(&mut self) -> Result<(), Error> {
+        self.write_content(format_args!("{}\n", sys::pid()))
+    }
+
+    /// Writes the given content into the locked file, replacing any previous
+    /// content, and syncs it to disk. Sync errors are ignored unless the file
+    /// is durable. Unlocks the file on error.
+    fn write_content(
+        &mut self,
+        arguments: core::fmt::Arguments,
+    ) -> Result<(), Error> {
+        let result = self
+            .open_desc()
+            .and_then(|desc| {
+                sys::truncate(desc)
+                    .and_then(|_| fmt::Writer(desc).write_fmt(arguments))
+            })
+            .and_then(|()| {
+                let durable = self.options.durable;
+                let (backend, mut target) = self.backend_target();
+                match backend.sync(&mut target) {
+                    Err(error) if durable => Err(error),
+                    _ => Ok(()),
+                }
+            });
         if result.is_err() {
             let _ = self.unlock();
         }
-        result.map(|_| true)
+        result
+    }
+
+    /// Converts the shared lock owned by this handle into an exclusive lock.
+    /// Blocks while it is not possible to lock exclusively (i.e. someone else
+    /// owns a lock). Unlike calling [`LockFile::unlock`] followed by
+    /// [`LockFile::lock`], the file's content (e.g. a PID) is preserved.
+    ///
+    /// # Atomicity
+    ///
+    /// With [`Backend::Native`] (`flock` on Unix, `LockFileEx` on Windows), the
+    /// conversion is NOT atomic: the shared lock is released before the
+    /// exclusive lock is requested. In this window, another handle waiting for
+    /// the lock may acquire it first, and so this call blocks until that handle
+    /// unlocks. [`Backend::Ofd`] converts atomically instead. On error, the
+    /// lock is released, unless releasing it fails as well.
+    ///
+    /// # Deadlocks
+    ///
+    /// Since [`Backend::Ofd`] keeps the shared lock while waiting, two handles
+    /// sharing the lock which both call this method wait for each other
+    /// forever, as open file description locks have no deadlock detection.
+    /// Use [`LockFile::try_upgrade`] in that case, or unlock and lock again.
+    /// [`Backend::Posix`] detects it instead, and fails with
+    /// [`Error::Deadlock`] in one of the processes.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NotLocked`] if this handle does not own a shared
+    /// lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockMode};
+    ///
+    /// let mut file = LockFile::open("testfiles/upgrade.lock")?;
+    /// file.lock_shared()?;
+    /// read_stuff();
+    /// file.upgrade()?;
+    /// assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    /// write_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn read_stuff() {
+    /// #    // reading stuff here.
+    /// # }
+    /// # fn write_stuff() {
+    /// #    // writing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Misuse Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Error, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/upgrade_misuse.lock")?;
+    /// file.lock()?;
+    /// assert!(matches!(file.upgrade(), Err(Error::NotLocked)));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn upgrade(&mut self) -> Result<(), Error> {
+        if self.mode != Some(LockMode::Shared) {
+            return Err(Error::NotLocked);
+        }
+        let (backend, mut target) = self.backend_target();
+        match target.interruptible(|target| backend.upgrade(target)) {
+            Ok(()) => {
+                self.mode = Some(LockMode::Exclusive);
+                Ok(())
+            },
+            Err(error) => self.release_after_error(error),
+        }
+    }
+
+    /// Tries to convert the shared lock owned by this handle into an exclusive
+    /// lock. Does NOT block if it is not possible to lock exclusively (i.e.
+    /// someone else owns a lock). Returns whether the conversion succeeded. The
+    /// file's content is preserved.
+    ///
+    /// # Atomicity
+    ///
+    /// With [`Backend::Native`] (`flock` on Unix, `LockFileEx` on Windows), the
+    /// shared lock is released before the exclusive lock is requested, so a
+    /// failed attempt leaves this handle unlocked, and another handle may
+    /// acquire the lock in the meantime. Check [`LockFile::lock_mode`] after a
+    /// failed attempt. [`Backend::Ofd`] converts atomically and keeps the
+    /// shared lock on failure instead. On error, the lock is released, unless
+    /// releasing it fails as well.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NotLocked`] if this handle does not own a shared
+    /// lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/try_upgrade.lock")?;
+    /// file.lock_shared()?;
+    /// if file.try_upgrade()? {
+    ///     write_stuff();
+    /// }
+    /// if file.owns_lock() {
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn write_stuff() {
+    /// #    // writing stuff here.
+    /// # }
+    /// ```
+    pub fn try_upgrade(&mut self) -> Result<bool, Error> {
+        if self.mode != Some(LockMode::Shared) {
+            return Err(Error::NotLocked);
+        }
+        let (backend, mut target) = self.backend_target();
+        match target.interruptible(|target| backend.try_upgrade(target)) {
+            Ok(true) => {
+                self.mode = Some(LockMode::Exclusive);
+                Ok(true)
+            },
+            Ok(false) => {
+                if !backend.capabilities().atomic_conversion {
+                    self.mode = None;
+                }
+                Ok(false)
+            },
+            Err(error) => self.release_after_error(error),
+        }
+    }
+
+    /// Converts the exclusive lock owned by this handle into a shared lock,
+    /// letting other readers in. Unlike calling [`LockFile::unlock`] followed
+    /// by [`LockFile::lock_shared`], the file's content (e.g. a PID) is
+    /// preserved.
+    ///
+    /// # Atomicity
+    ///
+    /// With [`Backend::Native`] on Unix (`flock`), the conversion is NOT
+    /// atomic: the exclusive lock is released before the shared lock is
+    /// requested, so a handle waiting for an exclusive lock may acquire it
+    /// first, and this call blocks until that handle unlocks. On Windows, and
+    /// with [`Backend::Ofd`], the conversion is atomic. On error, the lock is
+    /// released, unless releasing it fails as well.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NotLocked`] if this handle does not own an
+    /// exclusive lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut writer = LockFile::open("testfiles/downgrade.lock")?;
+    /// let mut reader = LockFile::open("testfiles/downgrade.lock")?;
+    /// writer.lock_with_pid()?;
+    /// assert!(!reader.try_lock_shared()?);
+    /// writer.downgrade()?;
+    /// assert!(reader.try_lock_shared()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Misuse Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Error, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/downgrade_misuse.lock")?;
+    /// file.lock_shared()?;
+    /// assert!(matches!(file.downgrade(), Err(Error::NotLocked)));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn downgrade(&mut self) -> Result<(), Error> {
+        if self.mode != Some(LockMode::Exclusive) {
+            return Err(Error::NotLocked);
+        }
+        let (backend, mut target) = self.backend_target();
+        match target.interruptible(|target| backend.downgrade(target)) {
+            Ok(()) => {
+                self.mode = Some(LockMode::Shared);
+                Ok(())
+            },
+            Err(error) => self.release_after_error(error),
+        }
     }
 
     /// Returns whether this file handle owns the lock.
@@ -357,14 +1062,40 @@ impl LockFile {
     /// # }
     /// ```
     pub fn owns_lock(&self) -> bool {
-        self.locked
+        self.mode.is_some()
+    }
+
+    /// Returns the mode of the lock this file handle owns, or `None` if it does
+    /// not own the lock.
+    ///
+    /// # Example
+    /// ```
+    /// use fslock::{LockFile, LockMode};
+    /// # fn main() -> Result<(), fslock::Error> {
+    ///
+    /// let mut file = LockFile::open("testfiles/lockmode.lock")?;
+    /// assert_eq!(file.lock_mode(), None);
+    /// file.lock_shared()?;
+    /// assert_eq!(file.lock_mode(), Some(LockMode::Shared));
+    /// file.unlock()?;
+    /// file.lock()?;
+    /// assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_mode(&self) -> Option<LockMode> {
+        self.mode
     }
 
     /// Unlocks this file. This file handle must own the file lock. If not
-    /// called manually, it is automatically called on `drop`.
+    /// called manually, it is automatically called on `drop`. If the lock was
+    /// exclusive, the file's content (e.g. a PID) is erased; shared locks leave
+    /// the content untouched, since other readers may still rely on it.
     ///
-    /// # Panics
-    /// Panics if this handle does not own the file.
+    /// # Errors
+    /// Fails with [`Error::NotLocked`] if this handle does not own the file.
     ///
     /// # Example
     ///
@@ -384,35 +1115,98 @@ impl LockFile {
     /// # }
     /// ```
     ///
-    /// # Panicking Example
+    /// # Misuse Example
     ///
-    /// ```should_panic
+    /// ```
     /// # fn main() -> Result<(), fslock::Error> {
-    /// use fslock::LockFile;
+    /// use fslock::{Error, LockFile};
     ///
     /// let mut file = LockFile::open("testfiles/endinglock.lock")?;
-    /// file.unlock()?;
+    /// assert!(matches!(file.unlock(), Err(Error::NotLocked)));
     ///
     /// # Ok(())
     /// # }
     /// ```
     pub fn unlock(&mut self) -> Result<(), Error> {
-        if !self.locked {
-            panic!("Attempted to unlock already unlocked lockfile");
+        let mode = match self.mode.take() {
+            Some(mode) => mode,
+            None => return Err(Error::NotLocked),
+        };
+        // The file is deleted and its content erased while still locked, so
+        // neither affects the next owner.
+        let delete_result = match mode {
+            LockMode::Exclusive if self.options.delete_on_unlock => {
+                sys::remove(self.lock_path())
+            },
+            _ => Ok(()),
+        };
+        let truncate_result = match (mode, self.desc) {
+            (LockMode::Exclusive, Some(desc)) if self.truncates_on_unlock() => {
+                sys::truncate(desc)
+            },
+            _ => Ok(()),
+        };
+        let (backend, mut target) = self.backend_target();
+        if let Err(error) =
+            target.interruptible(|target| backend.unlock(target))
+        {
+            // The lock is still held, so unlocking may be attempted again.
+            self.mode = Some(mode);
+            return Err(error);
         }
-        self.locked = false;
-        sys::unlock(self.desc)?;
-        sys::truncate(self.desc)?;
-        Ok(())
+        if mode == LockMode::Shared && self.options.delete_on_unlock {
+            self.delete_if_unused()?;
+        }
+        delete_result.and(truncate_result)
+    }
+
+    /// Releases the lock owned by this handle after the given error converting
+    /// it, keeping it if releasing fails as well. Returns the given error.
+    fn release_after_error<T>(&mut self, error: Error) -> Result<T, Error> {
+        let (backend, mut target) = self.backend_target();
+        if target.interruptible(|target| backend.unlock(target)).is_ok() {
+            self.mode = None;
+        }
+        Err(error)
+    }
+
+    /// Returns whether unlocking an exclusive lock erases the file's content.
+    fn truncates_on_unlock(&self) -> bool {
+        self.options.truncate_on_unlock && !self.options.read_only
+    }
+
+    /// Deletes the file at the lock path if this handle can lock it
+    /// exclusively right away, i.e. no other handle holds a lock on it.
+    fn delete_if_unused(&mut self) -> Result<(), Error> {
+        let (backend, mut target) = self.backend_target();
+        let locked = target.interruptible(|target| {
+            backend.try_lock(target, LockMode::Exclusive)
+        })?;
+        if !locked {
+            return Ok(());
+        }
+        let delete_result = self
+            .open_desc()
+            .and_then(|desc| sys::same_file(self.lock_path(), desc))
+            .and_then(|same_file| {
+                if same_file {
+                    sys::remove(self.lock_path())
+                } else {
+                    Ok(())
+                }
+            });
+        let (backend, mut target) = self.backend_target();
+        target.interruptible(|target| backend.unlock(target))?;
+        delete_result
     }
 }
 
 impl Drop for LockFile {
     fn drop(&mut self) {
-        if self.locked {
+        if self.mode.is_some() {
             let _ = self.unlock();
         }
-        sys::close(self.desc);
+        self.close();
     }
 }
 
@@ -431,32 +1225,122 @@ unsafe impl Sync for LockFile {}
 
 #[cfg(all(unix, feature = "std"))]
 mod as_fd {
-    use crate::LockFile;
-    use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+    use crate::{Error, LockFile, OwnedLockGuard};
+    use std::os::fd::{
+        AsFd,
+        AsRawFd,
+        BorrowedFd,
+        FromRawFd,
+        IntoRawFd,
+        OwnedFd,
+        RawFd,
+    };
+    impl LockFile {
+        /// Borrows the file descriptor kept open by this handle, like
+        /// [`AsFd::as_fd`], but fails instead of panicking if there is none,
+        /// e.g. because this handle uses
+        /// [`Backend::Sentinel`](crate::Backend::Sentinel) and is unlocked.
+        ///
+        /// # Example
+        ///
+        /// ```
+        /// # fn main() -> Result<(), fslock::Error> {
+        /// use fslock::{Backend, LockFileOptions};
+        ///
+        /// let mut file = LockFileOptions::new()
+        ///     .backend(Backend::Sentinel)
+        ///     .open("testfiles/try_as_fd.lock")?;
+        /// assert!(file.try_as_fd().is_err());
+        /// file.lock()?;
+        /// assert!(file.try_as_fd().is_ok());
+        /// file.unlock()?;
+        ///
+        /// # Ok(())
+        /// # }
+        /// ```
+        pub fn try_as_fd(&self) -> Result<BorrowedFd<'_>, Error> {
+            let fd = self.open_desc()?;
+            // SAFETY: We will not close the file descriptor until
+            // self is dropped or unlocked, which requires a mutable borrow.
+            Ok(unsafe { BorrowedFd::borrow_raw(fd) })
+        }
+    }
     impl AsRawFd for LockFile {
+        /// # Panics
+        /// Panics if no file is kept open, see [`LockFile::try_as_fd`].
         fn as_raw_fd(&self) -> RawFd {
-            self.desc
+            self.desc.expect("Lock file is not open")
         }
     }
     impl AsFd for LockFile {
+        /// # Panics
+        /// Panics if no file is kept open, see [`LockFile::try_as_fd`].
         fn as_fd(&self) -> BorrowedFd<'_> {
-            // SAFETY: We will not close the file descriptor until
-            // self is dropped.
-            unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) }
+            self.try_as_fd().expect("Lock file is not open")
+        }
+    }
+    impl FromRawFd for LockFile {
+        unsafe fn from_raw_fd(fd: RawFd) -> Self {
+            Self::from_desc(fd)
+        }
+    }
+    impl From<OwnedFd> for LockFile {
+        fn from(fd: OwnedFd) -> Self {
+            Self::from_desc(fd.into_raw_fd())
+        }
+    }
+    impl IntoRawFd for LockFile {
+        /// Unlocks this file if this handle owns a lock, ignoring errors like
+        /// on drop, and releases ownership of the file descriptor kept open.
+        /// If unlocking fails, the descriptor is handed over still locked.
+        /// Returns `-1` if no file is kept open, which is always the case for
+        /// [`Backend::Sentinel`](crate::Backend::Sentinel) and
+        /// [`Backend::Mkdir`](crate::Backend::Mkdir), as they close it on
+        /// unlock. See [`LockFile::into_file`] for a fallible conversion.
+        fn into_raw_fd(self) -> RawFd {
+            self.into_desc().unwrap_or(-1)
+        }
+    }
+    impl AsRawFd for OwnedLockGuard {
+        fn as_raw_fd(&self) -> RawFd {
+            (**self).as_raw_fd()
+        }
+    }
+    impl AsFd for OwnedLockGuard {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            (**self).as_fd()
         }
     }
 }
 
 #[cfg(all(windows, feature = "std"))]
 mod as_handle {
-    use crate::LockFile;
+    use crate::{sys, Error, LockFile, OwnedLockGuard};
     use std::os::windows::io::{
         AsHandle,
         AsRawHandle,
         BorrowedHandle,
+        FromRawHandle,
+        IntoRawHandle,
+        OwnedHandle,
         RawHandle,
     };
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    impl LockFile {
+        /// Borrows the handle kept open by this handle, like
+        /// [`AsHandle::as_handle`], but fails instead of panicking if there is
+        /// none, e.g. because this handle uses
+        /// [`Backend::Sentinel`](crate::Backend::Sentinel) and is unlocked.
+        pub fn try_as_handle(&self) -> Result<BorrowedHandle<'_>, Error> {
+            let handle = self.open_desc()?;
+            // SAFETY: We will not close the handle until self is dropped or
+            // unlocked, which requires a mutable borrow.
+            Ok(unsafe { BorrowedHandle::borrow_raw(handle as RawHandle) })
+        }
+    }
     impl AsRawHandle for LockFile {
+        /// # Panics
+        /// Panics if no file is kept open, see [`LockFile::try_as_handle`].
         fn as_raw_handle(&self) -> RawHandle {
             // "desc" is a `HANDLE`, which is a a type alias for
             // *mut winapi::cytpes::c_void
@@ -466,14 +1350,47 @@ mod as_handle {
             //
             // Since these are both c_voids, and both are handled by the
             // underlying OS, this pointer cast is okay.
-            self.desc as RawHandle
+            //
+            self.desc.expect("Lock file is not open") as RawHandle
         }
     }
     impl AsHandle for LockFile {
+        /// # Panics
+        /// Panics if no file is kept open, see [`LockFile::try_as_handle`].
+        fn as_handle(&self) -> BorrowedHandle<'_> {
+            self.try_as_handle().expect("Lock file is not open")
+        }
+    }
+    impl FromRawHandle for LockFile {
+        unsafe fn from_raw_handle(handle: RawHandle) -> Self {
+            Self::from_desc(handle as sys::FileDesc)
+        }
+    }
+    impl From<OwnedHandle> for LockFile {
+        fn from(handle: OwnedHandle) -> Self {
+            Self::from_desc(handle.into_raw_handle() as sys::FileDesc)
+        }
+    }
+    impl IntoRawHandle for LockFile {
+        /// Unlocks this file if this handle owns a lock, ignoring errors like
+        /// on drop, and releases ownership of the handle kept open. If
+        /// unlocking fails, the handle is handed over still locked. Returns
+        /// `INVALID_HANDLE_VALUE` if no file is kept open, which is always the
+        /// case for [`Backend::Sentinel`](crate::Backend::Sentinel) and
+        /// [`Backend::Mkdir`](crate::Backend::Mkdir), as they close it on
+        /// unlock. See [`LockFile::into_file`] for a fallible conversion.
+        fn into_raw_handle(self) -> RawHandle {
+            self.into_desc().unwrap_or(INVALID_HANDLE_VALUE) as RawHandle
+        }
+    }
+    impl AsRawHandle for OwnedLockGuard {
+        fn as_raw_handle(&self) -> RawHandle {
+            (**self).as_raw_handle()
+        }
+    }
+    impl AsHandle for OwnedLockGuard {
         fn as_handle(&self) -> BorrowedHandle<'_> {
-            // SAFETY: We will not close the handle until
-            // self is dropped.
-            unsafe { BorrowedHandle::borrow_raw(self.as_raw_handle()) }
+            (**self).as_handle()
         }
     }
 }
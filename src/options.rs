@@ -0,0 +1,215 @@
+//! This module implements the options for opening lock files.
+
+use crate::{
+    sys,
+    Backend,
+    Error,
+    InterruptPolicy,
+    IntoOsString,
+    LockFile,
+    ToOsStr,
+};
+
+/// Options for opening a [`LockFile`], configured in the builder style of
+/// [`std::fs::OpenOptions`], which control how the file is locked, opened and
+/// unlocked.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFileOptions;
+///
+/// let mut file = LockFileOptions::new()
+///     .mode(0o600)
+///     .nofollow(true)
+///     .open("testfiles/private.lock")?;
+/// file.lock_with_pid()?;
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockFileOptions {
+    pub(crate) backend: Backend,
+    pub(crate) delete_on_unlock: bool,
+    pub(crate) create: bool,
+    pub(crate) create_new: bool,
+    pub(crate) mode: u32,
+    pub(crate) cloexec: bool,
+    pub(crate) nofollow: bool,
+    pub(crate) read_only: bool,
+    pub(crate) truncate_on_unlock: bool,
+    pub(crate) durable: bool,
+    pub(crate) interrupt: InterruptPolicy,
+}
+
+impl LockFileOptions {
+    /// Creates the default options, which open the file like
+    /// [`LockFile::open`]: for reading and writing, creating it with mode
+    /// `0644` if needed, not inherited by child processes, and truncated when
+    /// unlocking an exclusive lock.
+    pub fn new() -> Self {
+        Self {
+            backend: Backend::Native,
+            delete_on_unlock: false,
+            create: true,
+            create_new: false,
+            mode: 0o644,
+            cloexec: true,
+            nofollow: false,
+            read_only: false,
+            truncate_on_unlock: true,
+            durable: false,
+            interrupt: InterruptPolicy::Retry,
+        }
+    }
+
+    /// Sets the mechanism used to lock the file. Fails when opening if the
+    /// backend is not available on the current platform. [`Backend::Native`]
+    /// by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{Backend, LockFileOptions};
+    ///
+    /// let backend = if Backend::Ofd.is_available() {
+    ///     Backend::Ofd
+    /// } else {
+    ///     Backend::Native
+    /// };
+    /// let mut file = LockFileOptions::new()
+    ///     .backend(backend)
+    ///     .open("testfiles/options_backend.lock")?;
+    /// file.lock()?;
+    /// assert_eq!(file.backend(), backend);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets whether to delete the file when unlocking it, so lock files do not
+    /// accumulate. See [deleting on unlock](crate#deleting-on-unlock).
+    /// Supported on Unix only, and implied by backends whose lock is the file
+    /// itself, such as [`Backend::Sentinel`]. Disabled by default.
+    pub fn delete_on_unlock(&mut self, delete_on_unlock: bool) -> &mut Self {
+        self.delete_on_unlock = delete_on_unlock;
+        self
+    }
+
+    /// Sets whether to create the file if it does not exist. Otherwise,
+    /// opening a missing file fails. Enabled by default.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets whether to create the file, failing if it already exists
+    /// (`O_CREAT | O_EXCL` on Unix, `CREATE_NEW` on Windows). Overrides
+    /// [`LockFileOptions::create`]. Disabled by default.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets the permissions of the file when created, before the process's
+    /// umask is applied. With [`Backend::Mkdir`], the lock directory is
+    /// created with these permissions as well, plus search permission wherever
+    /// read permission is given. Unix only, ignored on Windows. `0o644` by
+    /// default.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets whether the file is closed in child processes created through
+    /// `exec` (`O_CLOEXEC`), or not inherited by child processes on Windows.
+    /// Enabled by default.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Sets whether opening fails if the path is a symbolic link
+    /// (`O_NOFOLLOW`), preventing symbolic link attacks in world-writable
+    /// directories. On Windows, a symbolic link is opened itself instead of
+    /// its target (`FILE_FLAG_OPEN_REPARSE_POINT`). Disabled by default.
+    pub fn nofollow(&mut self, nofollow: bool) -> &mut Self {
+        self.nofollow = nofollow;
+        self
+    }
+
+    /// Sets whether to open the file for reading only, e.g. to hold shared
+    /// locks on a file this process may not write. The content is then never
+    /// written, and so locking with a PID fails, and unlocking does not
+    /// truncate the file. With [`Backend::Ofd`] and [`Backend::Posix`],
+    /// exclusive locks fail as well. Disabled by default.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets whether to erase the file's content (e.g. a PID) when unlocking an
+    /// exclusive lock. Enabled by default.
+    pub fn truncate_on_unlock(
+        &mut self,
+        truncate_on_unlock: bool,
+    ) -> &mut Self {
+        self.truncate_on_unlock = truncate_on_unlock;
+        self
+    }
+
+    /// Sets whether writes into the file are durable, for crash forensics:
+    /// the content written by [`LockFile::lock_with_pid`] and its variants is
+    /// synced to disk, and the directory entry of a file just created is
+    /// synced as well, so after a power loss the file still tells who held
+    /// the lock. Write and sync errors are then reported to the caller, which
+    /// no longer owns the lock, instead of ignored. Disabled by default.
+    pub fn durable(&mut self, durable: bool) -> &mut Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Sets what to do when locking, unlocking or converting a lock is
+    /// interrupted by a signal. See [`InterruptPolicy`]. Retries by default.
+    pub fn interrupt_policy(&mut self, policy: InterruptPolicy) -> &mut Self {
+        self.interrupt = policy;
+        self
+    }
+
+    /// Opens a file for locking with these options. Fails if the backend is
+    /// not available on the current platform. See [`LockFile::open`].
+    ///
+    /// # Errors
+    /// Fails with [`Error::InvalidPath`] if the path contains a nul-byte in a
+    /// place other than the end.
+    pub fn open<P>(&self, path: &P) -> Result<LockFile, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        if !self.backend.is_available() {
+            return Err(sys::unsupported());
+        }
+        if self.delete_on_unlock && !cfg!(unix) {
+            return Err(sys::unsupported());
+        }
+        let path = path.to_os_str()?.into_os_string()?;
+        let desc = self.backend.implementation().open(path.as_ref(), self)?;
+        let mut options = *self;
+        // Backends keeping no file open already remove it on unlock.
+        options.delete_on_unlock &= desc.is_some();
+        Ok(LockFile { mode: None, desc, options, path: Some(path) })
+    }
+}
+
+impl Default for LockFileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,576 @@
+//! This module implements recording and inspection of the owner of a lock
+//! file.
+
+use crate::{sys, Error, LockFile, Pid, ToOsStr};
+use core::{convert::TryFrom, fmt, str};
+
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maximum length of the lines holding a PID and a start time, newlines
+/// included.
+const IDENTITY_SIZE: usize = 64;
+
+/// Maximum length of a host name or executable path recorded in a lock file.
+const NAME_SIZE: usize = 1024;
+
+/// Maximum size of owner information read back from a lock file.
+#[cfg(feature = "std")]
+const INFO_SIZE: usize = 8 * 1024;
+
+/// A value of an owner record, displayed as UTF-8 without line breaks.
+#[derive(Debug, Clone, Copy)]
+struct Value<'bytes>(&'bytes [u8]);
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            for ch in chunk.valid().chars() {
+                match ch {
+                    '\n' | '\r' => {
+                        write!(fmt, "{}", char::REPLACEMENT_CHARACTER)?
+                    },
+                    _ => write!(fmt, "{}", ch)?,
+                }
+            }
+            if !chunk.invalid().is_empty() {
+                write!(fmt, "{}", char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Owner information of the current process, as written into a lock file.
+#[derive(Debug, Clone, Copy)]
+struct Record<'buf> {
+    /// ID of the current process.
+    pid: Pid,
+    /// Start time of the current process, if available.
+    start_time: Option<u64>,
+    /// Host name, if available.
+    hostname: Option<&'buf [u8]>,
+    /// Path of the current executable, if available.
+    exe: Option<&'buf [u8]>,
+    /// Current time, in seconds since the Unix epoch.
+    acquired_at: u64,
+    /// User-supplied tag.
+    tag: Option<&'buf str>,
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "pid={}", self.pid)?;
+        if let Some(start_time) = self.start_time {
+            writeln!(fmt, "start_time={}", start_time)?;
+        }
+        if let Some(hostname) = self.hostname {
+            writeln!(fmt, "hostname={}", Value(hostname))?;
+        }
+        if let Some(exe) = self.exe {
+            writeln!(fmt, "exe={}", Value(exe))?;
+        }
+        writeln!(fmt, "acquired_at={}", self.acquired_at)?;
+        if let Some(tag) = self.tag {
+            writeln!(fmt, "tag={}", Value(tag.as_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a decimal integer made only of ASCII digits.
+fn parse_u64(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut number: u64 = 0;
+    for &byte in digits {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        number = number.checked_mul(10)?.checked_add(u64::from(byte - b'0'))?;
+    }
+    Some(number)
+}
+
+/// Parses a PID from the first line of a lock file's content, either as
+/// written by [`LockFile::lock_with_pid`] or by
+/// [`LockFile::lock_with_owner_info`]. Surrounding whitespace is ignored.
+fn parse_pid(content: &[u8]) -> Option<Pid> {
+    let line = match content.iter().position(|&byte| byte == b'\n') {
+        Some(end) => &content[.. end],
+        None => content,
+    };
+    let line = line.trim_ascii();
+    let line = line.strip_prefix(b"pid=").unwrap_or(line);
+    Pid::try_from(parse_u64(line)?).ok()
+}
+
+/// Parses the start time from the second line of a lock file's content, as
+/// written by [`LockFile::lock_with_owner_info`]. The line must be complete.
+fn parse_start_time(content: &[u8]) -> Option<u64> {
+    let mut lines = content.split(|&byte| byte == b'\n');
+    lines.next()?;
+    let line = lines.next()?;
+    // Without a following line, the newline was not read.
+    lines.next()?;
+    parse_u64(line.trim_ascii().strip_prefix(b"start_time=")?)
+}
+
+/// Reads and parses the PID and the start time, if recorded, at the start of
+/// the given open file.
+fn read_identity(
+    desc: sys::FileDesc,
+) -> Result<Option<(Pid, Option<u64>)>, Error> {
+    let mut buf = [0; IDENTITY_SIZE];
+    let len = sys::read_at(desc, 0, &mut buf)?;
+    let content = &buf[.. len];
+    if len == buf.len() && !content.contains(&b'\n') {
+        return Ok(None);
+    }
+    Ok(parse_pid(content).map(|pid| (pid, parse_start_time(content))))
+}
+
+/// Reads and parses the PID at the start of the given open file.
+fn read_pid(desc: sys::FileDesc) -> Result<Option<Pid>, Error> {
+    Ok(read_identity(desc)?.map(|(pid, _)| pid))
+}
+
+/// Returns whether the process with the given ID and start time is running.
+/// Without a start time, or on platforms which do not record it, only the PID
+/// is checked.
+fn is_identity_alive(pid: Pid, start_time: Option<u64>) -> Result<bool, Error> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if let Some(start_time) = start_time {
+            return is_identity_alive_in(b"/proc", pid, start_time);
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = start_time;
+    sys::is_alive(pid)
+}
+
+/// Returns whether the process with the given ID is running and started at the
+/// given time, according to the procfs mounted at the given path. A process
+/// hidden from the procfs, e.g. one of another user with `hidepid`, is only
+/// checked by its ID, so it is never mistaken for a dead one.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn is_identity_alive_in(
+    procfs: &[u8],
+    pid: Pid,
+    start_time: u64,
+) -> Result<bool, Error> {
+    match sys::process_start_time(procfs, pid)? {
+        Some(found) => Ok(found == start_time),
+        None => sys::is_alive(pid),
+    }
+}
+
+/// Reads the PID recorded by [`LockFile::lock_with_pid`] in the lock file at
+/// the given path, without creating nor locking it. Returns `None` if the file
+/// does not start with a PID, e.g. because it is not locked.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+///
+/// let mut file = LockFile::open("testfiles/owner_pid.lock")?;
+/// file.lock_with_pid()?;
+/// let pid = fslock::owner_pid("testfiles/owner_pid.lock")?;
+/// assert_eq!(pid, Some(std::process::id() as fslock::Pid));
+/// file.unlock()?;
+/// assert_eq!(fslock::owner_pid("testfiles/owner_pid.lock")?, None);
+///
+/// # Ok(())
+/// # }
+/// ```
+pub fn owner_pid<P>(path: &P) -> Result<Option<Pid>, Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    let path = path.to_os_str()?;
+    let desc = sys::open_read(path.as_ref())?;
+    let result = read_pid(desc);
+    sys::close(desc);
+    result
+}
+
+/// Returns whether a process with the given ID is still running. On Unix, this
+/// sends it the null signal with `kill(pid, 0)`. A process owned by another
+/// user is still reported as running.
+///
+/// Note that PIDs may be reused by the operating system, so a running process
+/// may not be the one which recorded its PID. [`LockOwnerInfo::is_alive`] and
+/// [`LockFile::is_owner_alive`] also compare the process start time, when
+/// recorded.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// let pid = std::process::id() as fslock::Pid;
+/// assert!(fslock::is_pid_alive(pid)?);
+///
+/// # Ok(())
+/// # }
+/// ```
+pub fn is_pid_alive(pid: Pid) -> Result<bool, Error> {
+    sys::is_alive(pid)
+}
+
+impl LockFile {
+    /// Calls the given function over the file holding the content written by
+    /// the owner of this lock: the file kept open by this handle, or else the
+    /// file opened by the backend for reading. Returns `None` if there is no
+    /// such file.
+    pub(crate) fn read_content<F, T>(&self, read: F) -> Result<Option<T>, Error>
+    where
+        F: FnOnce(sys::FileDesc) -> Result<T, Error>,
+    {
+        if let Some(desc) = self.desc {
+            return read(desc).map(Some);
+        }
+        let backend = self.options.backend.implementation();
+        match backend.open_content(self.lock_path())? {
+            Some(desc) => {
+                let result = read(desc);
+                sys::close(desc);
+                result.map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the PID recorded by [`LockFile::lock_with_pid`] in this file,
+    /// possibly by another handle or process. Returns `None` if the file does
+    /// not start with a PID, e.g. because it is not locked. Does not require
+    /// this handle to own the lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/read_owner_pid.lock")?;
+    /// let mut other = LockFile::open("testfiles/read_owner_pid.lock")?;
+    /// file.lock_with_pid()?;
+    /// if !other.try_lock()? {
+    ///     if let Some(pid) = other.read_owner_pid()? {
+    ///         println!("locked by PID {}", pid);
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_owner_pid(&self) -> Result<Option<Pid>, Error> {
+        Ok(self.read_content(read_pid)?.flatten())
+    }
+
+    /// Returns whether the process whose PID is recorded in this file is still
+    /// running. Returns `false` if no PID is recorded. If the process start
+    /// time was recorded by [`LockFile::lock_with_owner_info`], it must match
+    /// as well, so a PID reused by another process is reported as dead. See
+    /// [`LockFile::read_owner_pid`] and [`is_pid_alive`].
+    ///
+    /// This is only meaningful on the host which recorded the owner.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/is_owner_alive.lock")?;
+    /// assert!(!file.is_owner_alive()?);
+    /// file.lock_with_pid()?;
+    /// assert!(file.is_owner_alive()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_owner_alive(&self) -> Result<bool, Error> {
+        match self.read_content(read_identity)?.flatten() {
+            Some((pid, start_time)) => is_identity_alive(pid, start_time),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Information about the owner of a lock, as recorded in the lock file by
+/// [`LockFile::lock_with_owner_info`]. See the
+/// [format](crate#owner-information) description. Content written by
+/// [`LockFile::lock_with_pid`] is parsed as well, with only the PID available.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOwnerInfo {
+    /// ID of the owner process.
+    pub pid: Pid,
+    /// Name of the host the owner runs on.
+    pub hostname: Option<String>,
+    /// Start time of the owner process, in clock ticks since boot. Recorded on
+    /// Linux and Android only.
+    pub start_time: Option<u64>,
+    /// Path of the owner's executable.
+    pub exe: Option<String>,
+    /// When the lock was acquired.
+    pub acquired_at: Option<SystemTime>,
+    /// Free-form text supplied by the owner.
+    pub tag: Option<String>,
+}
+
+#[cfg(feature = "std")]
+impl LockOwnerInfo {
+    /// Returns whether the owner is still running. If the start time was
+    /// recorded, it must match the running process's as well, so a PID reused
+    /// by another process is reported as dead. Otherwise, only the PID is
+    /// checked, like [`is_pid_alive`].
+    ///
+    /// This is only meaningful on the host which recorded the owner.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/owner_info_alive.lock")?;
+    /// file.lock_with_owner_info(None)?;
+    /// let info = file.read_owner_info()?.unwrap();
+    /// assert!(info.is_alive()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_alive(&self) -> Result<bool, Error> {
+        is_identity_alive(self.pid, self.start_time)
+    }
+
+    /// Parses owner information from the content of a lock file. Returns
+    /// `None` if the content does not start with a PID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fslock::LockOwnerInfo;
+    ///
+    /// let info = LockOwnerInfo::parse(b"pid=42\nhostname=box\ntag=a b\n");
+    /// let info = info.unwrap();
+    /// assert_eq!(info.pid, 42);
+    /// assert_eq!(info.hostname.as_deref(), Some("box"));
+    /// assert_eq!(info.tag.as_deref(), Some("a b"));
+    /// assert_eq!(info.exe, None);
+    ///
+    /// let info = LockOwnerInfo::parse(b"42\n").unwrap();
+    /// assert_eq!(info.pid, 42);
+    /// ```
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        let mut info = Self {
+            pid: parse_pid(content)?,
+            hostname: None,
+            start_time: None,
+            exe: None,
+            acquired_at: None,
+            tag: None,
+        };
+
+        let text = String::from_utf8_lossy(content);
+        for line in text.lines().skip(1) {
+            let (key, value) = match line.split_once('=') {
+                Some(entry) => entry,
+                None => continue,
+            };
+            match key.trim() {
+                "hostname" => info.hostname = Some(value.to_owned()),
+                "start_time" => {
+                    info.start_time = parse_u64(value.trim().as_bytes())
+                },
+                "exe" => info.exe = Some(value.to_owned()),
+                "acquired_at" => {
+                    info.acquired_at = parse_u64(value.trim().as_bytes())
+                        .and_then(|secs| {
+                            UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+                        })
+                },
+                "tag" => info.tag = Some(value.to_owned()),
+                _ => (),
+            }
+        }
+
+        Some(info)
+    }
+}
+
+/// Reads and parses the owner information of the given open file.
+#[cfg(feature = "std")]
+pub(crate) fn read_info(
+    desc: sys::FileDesc,
+) -> Result<Option<LockOwnerInfo>, Error> {
+    let mut buf = vec![0; INFO_SIZE];
+    let len = sys::read_at(desc, 0, &mut buf)?;
+    Ok(LockOwnerInfo::parse(&buf[.. len]))
+}
+
+/// Reads the owner information recorded by [`LockFile::lock_with_owner_info`]
+/// (or the PID recorded by [`LockFile::lock_with_pid`]) in the lock file at
+/// the given path, without creating nor locking it. Returns `None` if the file
+/// does not start with a PID, e.g. because it is not locked.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+///
+/// let mut file = LockFile::open("testfiles/owner_info.lock")?;
+/// file.lock_with_owner_info(Some("example"))?;
+/// let info = fslock::owner_info("testfiles/owner_info.lock")?.unwrap();
+/// assert_eq!(info.pid, std::process::id() as fslock::Pid);
+/// assert_eq!(info.tag.as_deref(), Some("example"));
+///
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub fn owner_info<P>(path: &P) -> Result<Option<LockOwnerInfo>, Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    let path = path.to_os_str()?;
+    let desc = sys::open_read(path.as_ref())?;
+    let result = read_info(desc);
+    sys::close(desc);
+    result
+}
+
+/// Calls the given function with the owner information of this process and
+/// the given tag.
+fn with_record<F, T>(tag: Option<&str>, function: F) -> T
+where
+    F: FnOnce(&Record) -> T,
+{
+    let mut hostname = [0; NAME_SIZE];
+    let hostname_len = sys::hostname(&mut hostname);
+    let mut exe = [0; NAME_SIZE];
+    let exe_len = sys::exe_path(&mut exe);
+    let record = Record {
+        pid: sys::pid(),
+        start_time: sys::start_time(),
+        hostname: hostname_len.map(|len| &hostname[.. len]),
+        exe: exe_len.map(|len| &exe[.. len]),
+        acquired_at: sys::now_unix(),
+        tag,
+    };
+    function(&record)
+}
+
+/// Writes information about this process into the given empty file, such as a
+/// file just created to hold a lock.
+pub(crate) fn write_owner_record(desc: sys::FileDesc) -> Result<(), Error> {
+    with_record(None, |record| {
+        crate::fmt::Writer(desc).write_fmt(format_args!("{}", record))
+    })
+}
+
+impl LockFile {
+    /// Locks this file and writes information about this process into the
+    /// file, which will be erased on unlock: PID, host name, process start
+    /// time, executable path, acquisition time and the given tag, following
+    /// the [format](crate#owner-information) read back by [`LockOwnerInfo`].
+    /// Line breaks in the tag are replaced. Like [`LockFile::lock`], blocks
+    /// while it is not possible to lock. After locked, if no attempt to
+    /// unlock is made, it will be automatically unlocked on the file handle
+    /// drop.
+    ///
+    /// Writing does not allocate, so it is available without the `std`
+    /// feature.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/with_owner_info.lock")?;
+    /// file.lock_with_owner_info(Some("nightly build"))?;
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// let info = file.read_owner_info()?.unwrap();
+    /// assert_eq!(info.tag.as_deref(), Some("nightly build"));
+    /// # }
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with_owner_info(
+        &mut self,
+        tag: Option<&str>,
+    ) -> Result<(), Error> {
+        self.lock()?;
+        self.write_owner_info(tag)
+    }
+
+    /// Locks this file and writes information about this process into the
+    /// file, like [`LockFile::lock_with_owner_info`]. Does NOT block if it is
+    /// not possible to lock (i.e. someone else already owns a lock).
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn try_lock_with_owner_info(
+        &mut self,
+        tag: Option<&str>,
+    ) -> Result<bool, Error> {
+        if !self.try_lock()? {
+            return Ok(false);
+        }
+        self.write_owner_info(tag).map(|_| true)
+    }
+
+    /// Writes information about this process into the locked file, replacing
+    /// any previous content. Unlocks the file on error.
+    pub(crate) fn write_owner_info(
+        &mut self,
+        tag: Option<&str>,
+    ) -> Result<(), Error> {
+        with_record(tag, |record| {
+            self.write_content(format_args!("{}", record))
+        })
+    }
+
+    /// Reads the owner information recorded by
+    /// [`LockFile::lock_with_owner_info`] (or the PID recorded by
+    /// [`LockFile::lock_with_pid`]) in this file, possibly by another handle or
+    /// process. Returns `None` if the file does not start with a PID, e.g.
+    /// because it is not locked. Does not require this handle to own the lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/read_owner_info.lock")?;
+    /// let mut other = LockFile::open("testfiles/read_owner_info.lock")?;
+    /// file.lock_with_owner_info(None)?;
+    /// if !other.try_lock()? {
+    ///     if let Some(info) = other.read_owner_info()? {
+    ///         println!("locked by PID {} on {:?}", info.pid, info.hostname);
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_owner_info(&self) -> Result<Option<LockOwnerInfo>, Error> {
+        Ok(self.read_content(read_info)?.flatten())
+    }
+}
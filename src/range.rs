@@ -0,0 +1,98 @@
+//! This module implements locks over byte ranges of a file.
+
+use crate::{Error, LockFile, LockMode};
+
+impl LockFile {
+    /// Locks the region of this file starting at the given offset with the
+    /// given length in bytes, in the given mode. A length of zero extends the
+    /// region indefinitely. Blocks while it is not possible to lock (i.e.
+    /// someone else owns an overlapping lock). With
+    /// [`Backend::Native`](crate::Backend::Native), range locks are
+    /// independent from whole-file locks. See [byte-range
+    /// locks](crate#byte-range-locks) for how regions held by the same handle
+    /// interact. Fails if the backend does not support range locks (see
+    /// [`Capabilities::ranges`](crate::Capabilities::ranges)). After locked,
+    /// if no attempt to unlock is made, it will be automatically unlocked on
+    /// the file handle drop.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockMode};
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_range.lock")?;
+    /// file.lock_range(4096, 4096, LockMode::Exclusive)?;
+    /// // update the second page here.
+    /// file.unlock_range(4096, 4096)?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_range(
+        &mut self,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        let (backend, mut target) = self.backend_target();
+        target.interruptible(|target| {
+            backend.lock_range(target, offset, len, mode)
+        })
+    }
+
+    /// Locks the region of this file starting at the given offset with the
+    /// given length in bytes, in the given mode, returning as soon as possible
+    /// if it is not possible to lock. Returns whether the region was locked.
+    /// See [`LockFile::lock_range`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockMode};
+    ///
+    /// let mut file = LockFile::open("testfiles/try_lock_range.lock")?;
+    /// if file.try_lock_range(0, 512, LockMode::Shared)? {
+    ///     // read the header here.
+    ///     file.unlock_range(0, 512)?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock_range(
+        &mut self,
+        offset: u64,
+        len: u64,
+        mode: LockMode,
+    ) -> Result<bool, Error> {
+        let (backend, mut target) = self.backend_target();
+        target.interruptible(|target| {
+            backend.try_lock_range(target, offset, len, mode)
+        })
+    }
+
+    /// Unlocks the region of this file starting at the given offset with the
+    /// given length in bytes. On Unix, any part of a locked region may be
+    /// unlocked, while on Windows, the region must be the same as the one
+    /// locked. See [byte-range locks](crate#byte-range-locks).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockMode};
+    ///
+    /// let mut file = LockFile::open("testfiles/unlock_range.lock")?;
+    /// file.lock_range(0, 100, LockMode::Exclusive)?;
+    /// file.unlock_range(0, 100)?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unlock_range(&mut self, offset: u64, len: u64) -> Result<(), Error> {
+        let (backend, mut target) = self.backend_target();
+        target.interruptible(|target| backend.unlock_range(target, offset, len))
+    }
+}
@@ -0,0 +1,448 @@
+//! This module implements breaking stale locks, whose owner is gone.
+
+use crate::{
+    owner::read_info,
+    sys,
+    timeout::Backoff,
+    Backend,
+    Error,
+    LockFile,
+    LockFileOptions,
+    LockMode,
+    LockOwnerInfo,
+    LockTarget,
+    OsStr,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Suffix of the auxiliary lock file serializing the breaking of stale locks.
+const BREAK_SUFFIX: &str = ".break";
+
+/// Suffix of the file replacing a broken lock file before being renamed.
+const NEW_SUFFIX: &str = ".break.new";
+
+/// A policy deciding when a lock held by someone else is stale, and so may be
+/// forcibly broken by [`LockFile::lock_or_break_stale`]. A lock is only ever
+/// considered stale if its owner was recorded in the file, by
+/// [`LockFile::lock_with_pid`], [`LockFile::lock_with_owner_info`] or
+/// [`LockFile::lock_or_break_stale`] itself.
+///
+/// # Example
+///
+/// ```
+/// use fslock::StalePolicy;
+/// use std::time::Duration;
+///
+/// let policy = StalePolicy::dead_owner().max_age(Duration::from_secs(3600));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StalePolicy {
+    /// Whether a lock whose owner is no longer running is stale.
+    dead_owner: bool,
+    /// How long after being acquired a lock becomes stale.
+    max_age: Option<Duration>,
+}
+
+impl StalePolicy {
+    /// A policy under which no lock is stale.
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    /// A policy under which a lock is stale if its recorded owner is no longer
+    /// running, according to [`LockOwnerInfo::is_alive`]. Since process IDs
+    /// are only meaningful on the host which recorded them, an owner recorded
+    /// with a host name other than this host's is never considered dead.
+    pub fn dead_owner() -> Self {
+        Self { dead_owner: true, ..Self::default() }
+    }
+
+    /// Makes a lock stale as well if it was acquired longer than the given
+    /// duration ago. The acquisition time is the one recorded in the owner
+    /// information, or the file's modification time if only a PID was
+    /// recorded.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        Self { max_age: Some(max_age), ..self }
+    }
+
+    /// Returns whether a lock with the given owner, whose file was last
+    /// modified at the given time, is stale under this policy.
+    fn is_stale(
+        &self,
+        owner: &LockOwnerInfo,
+        modified: SystemTime,
+    ) -> Result<bool, Error> {
+        if let Some(max_age) = self.max_age {
+            let acquired_at = owner.acquired_at.unwrap_or(modified);
+            let age = SystemTime::now()
+                .duration_since(acquired_at)
+                .unwrap_or(Duration::ZERO);
+            if age > max_age {
+                return Ok(true);
+            }
+        }
+        if self.dead_owner && is_local(owner) {
+            return Ok(!owner.is_alive()?);
+        }
+        Ok(false)
+    }
+}
+
+/// Returns whether the given owner is recorded as running on this host, or
+/// recorded without a host name.
+fn is_local(owner: &LockOwnerInfo) -> bool {
+    let hostname = match &owner.hostname {
+        Some(hostname) => hostname,
+        None => return true,
+    };
+    let mut buf = [0; 1024];
+    match sys::hostname(&mut buf) {
+        Some(len) => buf[.. len] == *hostname.as_bytes(),
+        None => true,
+    }
+}
+
+/// Runs the given function while holding the break lock at the given path,
+/// opened with the given options, in the given mode. The lock is exclusive if
+/// the backend has no shared locks.
+fn with_break_lock<F, T>(
+    break_path: &OsStr,
+    options: &LockFileOptions,
+    mode: LockMode,
+    function: F,
+) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let mode = if options.backend.capabilities().shared {
+        mode
+    } else {
+        LockMode::Exclusive
+    };
+    let mut break_file = options.open(break_path)?;
+    break_file.lock_with_mode(mode)?;
+    let result = function();
+    // Deleting the break file may fail if someone else is about to lock it,
+    // which does not affect the lock on this handle's file.
+    let _ = break_file.unlock();
+    result
+}
+
+impl LockFile {
+    /// Locks this file exclusively, like [`LockFile::lock_with_owner_info`],
+    /// but breaks the lock if it is held by someone else and the given policy
+    /// considers it stale. Blocks while it is not possible to lock and the
+    /// lock is not stale. Returns whether a stale lock was broken to acquire
+    /// this one. After locked, if no attempt to unlock is made, it will be
+    /// automatically unlocked on the file handle drop.
+    ///
+    /// A lock is broken by creating and locking a new lock file, which is then
+    /// renamed over the stale one, so the stale owner keeps a lock on a file
+    /// nobody else uses. Every handle acquiring a lock through this method
+    /// checks whether its file was replaced, and opens the new file in that
+    /// case.
+    ///
+    /// This is mostly useful for locks which the operating system does not
+    /// release when their owner dies, such as locks on network file systems or
+    /// lock files inherited by a child process which outlives its parent.
+    ///
+    /// # Races
+    ///
+    /// Breaking a lock and validating a freshly acquired lock are serialized
+    /// through an auxiliary lock file, named after the lock file with a
+    /// `.break` suffix, which is created next to it with the options of this
+    /// handle, and removed once unused on Unix. Thus, when many contenders
+    /// decide to break the same stale lock, only one of them breaks it, and
+    /// the others wait for the new lock instead. For this
+    /// guarantee, every contender must lock through this method: a handle
+    /// locked by other methods may still be left with a lock on a file which
+    /// has just been replaced. The new lock file is first created next to the
+    /// lock file as well, with a `.break.new` suffix, and removed if breaking
+    /// fails.
+    ///
+    /// On Windows, the stale lock file may not be replaced while the stale
+    /// owner keeps it open, in which case breaking the lock fails with an
+    /// error.
+    ///
+    /// # Lock file backends
+    ///
+    /// Backends whose lock is the existence of the lock file, such as
+    /// [`Backend::Sentinel`] and [`Backend::Mkdir`], keep no file open while
+    /// unlocked. With them, the owner is read from the lock file (or the
+    /// content file inside the lock directory), and a stale lock is renamed
+    /// away, with a `.break.old` suffix, and removed before the lock file is
+    /// created again, through
+    /// [`LockBackend::remove_stale`](crate::LockBackend::remove_stale). The
+    /// stale owner then leaves the new lock file in place when unlocking.
+    /// Their break lock uses [`Backend::Native`] instead of the handle's
+    /// backend, since a breaker crashing while holding it would otherwise
+    /// leave it behind.
+    ///
+    /// Fails if this handle was not opened from a path.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, StalePolicy};
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_or_break_stale.lock")?;
+    /// let broke = file.lock_or_break_stale(StalePolicy::dead_owner())?;
+    /// assert!(!broke);
+    /// assert!(file.is_owner_alive()?);
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_or_break_stale(
+        &mut self,
+        policy: StalePolicy,
+    ) -> Result<bool, Error> {
+        self.check_unlocked()?;
+        if self.path.is_none() {
+            return Err(sys::unsupported());
+        }
+        let break_path = self.lock_path().with_suffix(BREAK_SUFFIX)?;
+        let break_options = self.break_options();
+        if self.desc.is_none() {
+            return self.lock_or_remove_stale(
+                &break_path,
+                &break_options,
+                policy,
+            );
+        }
+        let mut backoff = Backoff::new();
+
+        loop {
+            if self.try_acquire(LockMode::Exclusive)? {
+                let result = with_break_lock(
+                    &break_path,
+                    &break_options,
+                    LockMode::Shared,
+                    || self.validate_acquired(),
+                );
+                match result {
+                    Ok(true) => return Ok(false),
+                    Ok(false) => self.reopen()?,
+                    Err(error) => {
+                        if self.mode.is_some() {
+                            let _ = self.release();
+                        }
+                        return Err(error);
+                    },
+                }
+                continue;
+            }
+
+            if !sys::same_file(self.lock_path(), self.open_desc()?)? {
+                self.reopen()?;
+                continue;
+            }
+
+            if self.read_stale(policy)? {
+                let result = with_break_lock(
+                    &break_path,
+                    &break_options,
+                    LockMode::Exclusive,
+                    || self.break_stale(policy),
+                );
+                if let Some(broke) = result? {
+                    return Ok(broke);
+                }
+            }
+
+            backoff.sleep_until(None);
+        }
+    }
+
+    /// Like [`LockFile::lock_or_break_stale`], for backends keeping no file
+    /// open while unlocked, whose stale locks are removed.
+    fn lock_or_remove_stale(
+        &mut self,
+        break_path: &OsStr,
+        break_options: &LockFileOptions,
+        policy: StalePolicy,
+    ) -> Result<bool, Error> {
+        let mut backoff = Backoff::new();
+        loop {
+            let acquired = with_break_lock(
+                break_path,
+                break_options,
+                LockMode::Shared,
+                || self.try_acquire_owned(),
+            )?;
+            if acquired {
+                return Ok(false);
+            }
+
+            if self.read_stale(policy)? {
+                let result = with_break_lock(
+                    break_path,
+                    break_options,
+                    LockMode::Exclusive,
+                    || self.remove_stale(policy),
+                );
+                if let Some(broke) = result? {
+                    return Ok(broke);
+                }
+            }
+
+            backoff.sleep_until(None);
+        }
+    }
+
+    /// Options of the auxiliary file serializing the breaking of stale locks:
+    /// the ones of this handle, so the permission mode, backend and interrupt
+    /// policy apply to it as well, but deleting the file on unlock on Unix.
+    /// Backends keeping no file open while unlocked are replaced by the native
+    /// one.
+    fn break_options(&self) -> LockFileOptions {
+        let mut options = self.options;
+        options.delete_on_unlock = cfg!(unix);
+        if self.desc.is_none() {
+            options.backend = Backend::Native;
+        }
+        options
+    }
+
+    /// Tries to lock this file exclusively, and records this process as its
+    /// owner if locked.
+    fn try_acquire_owned(&mut self) -> Result<bool, Error> {
+        if !self.try_acquire(LockMode::Exclusive)? {
+            return Ok(false);
+        }
+        if let Err(error) = self.write_owner_info(None) {
+            let _ = self.release();
+            return Err(error);
+        }
+        Ok(true)
+    }
+
+    /// Removes the lock on this handle's file if it is still stale, and then
+    /// locks the file. Returns whether a lock was removed, if this handle ends
+    /// up owning the lock. Must hold the break lock exclusively.
+    fn remove_stale(
+        &mut self,
+        policy: StalePolicy,
+    ) -> Result<Option<bool>, Error> {
+        // The owner released the lock in the meantime.
+        if self.try_acquire_owned()? {
+            return Ok(Some(false));
+        }
+        if !self.read_stale(policy)? {
+            return Ok(None);
+        }
+        let backend = self.options.backend.implementation();
+        let removed = backend.remove_stale(self.lock_path())?;
+        // Someone not breaking stale locks may acquire it first.
+        if self.try_acquire_owned()? {
+            Ok(Some(removed))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Checks whether the lock just acquired by this handle is on the file
+    /// currently at the lock path, and records this process as its owner if
+    /// so. Otherwise, releases it. Must hold the break lock, at least shared.
+    fn validate_acquired(&mut self) -> Result<bool, Error> {
+        if !sys::same_file(self.lock_path(), self.open_desc()?)? {
+            self.release()?;
+            return Ok(false);
+        }
+        self.write_owner_info(None)?;
+        Ok(true)
+    }
+
+    /// Breaks the lock on this handle's file if it is still stale, replacing
+    /// the file with a new one locked by this handle. Returns whether a lock
+    /// was broken, if this handle ends up owning the lock. Must hold the break
+    /// lock exclusively.
+    fn break_stale(
+        &mut self,
+        policy: StalePolicy,
+    ) -> Result<Option<bool>, Error> {
+        // Someone else broke the lock in the meantime.
+        if !sys::same_file(self.lock_path(), self.open_desc()?)? {
+            self.reopen()?;
+            return Ok(None);
+        }
+        // The owner released the lock in the meantime.
+        if self.try_acquire(LockMode::Exclusive)? {
+            self.write_owner_info(None)?;
+            return Ok(Some(false));
+        }
+        if !self.read_stale(policy)? {
+            return Ok(None);
+        }
+
+        // The new file is locked before taking the place of the stale one, so
+        // no one else acquires it first.
+        let new_path = self.lock_path().with_suffix(NEW_SUFFIX)?;
+        let options = self.options;
+        let backend = options.backend.implementation();
+        let mut desc = backend.open(&new_path, &options)?;
+        let mut target = LockTarget::new(&new_path, &options, &mut desc);
+        // Only the holder of the exclusive break lock uses the new file, so it
+        // is removed whenever it does not end up replacing the stale one.
+        match target.interruptible(|target| {
+            backend.try_lock(target, LockMode::Exclusive)
+        }) {
+            Ok(true) => (),
+            Ok(false) => {
+                backend.close(&mut target);
+                let _ = sys::remove(&new_path);
+                return Ok(None);
+            },
+            Err(error) => {
+                backend.close(&mut target);
+                let _ = sys::remove(&new_path);
+                return Err(error);
+            },
+        }
+        self.close();
+        self.desc = desc;
+        self.mode = Some(LockMode::Exclusive);
+
+        let result = self
+            .write_owner_info(None)
+            .and_then(|()| sys::rename(&new_path, self.lock_path()));
+        if let Err(error) = result {
+            if self.mode.is_some() {
+                let _ = self.unlock();
+            }
+            let _ = sys::remove(&new_path);
+            let _ = self.reopen();
+            return Err(error);
+        }
+        Ok(Some(true))
+    }
+
+    /// Reads the owner recorded in this handle's file and checks whether the
+    /// given policy considers it stale.
+    fn read_stale(&self, policy: StalePolicy) -> Result<bool, Error> {
+        let owner = self.read_content(|desc| match read_info(desc)? {
+            Some(owner) => Ok(Some((owner, sys::modified_unix(desc)?))),
+            None => Ok(None),
+        })?;
+        match owner.flatten() {
+            Some((owner, modified)) => {
+                let modified = UNIX_EPOCH + Duration::from_secs(modified);
+                policy.is_stale(&owner, modified)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Releases the lock owned by this handle, keeping the file's content.
+    fn release(&mut self) -> Result<(), Error> {
+        let (backend, mut target) = self.backend_target();
+        target.interruptible(|target| backend.unlock(target))?;
+        self.mode = None;
+        Ok(())
+    }
+}
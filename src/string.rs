@@ -1,6 +1,9 @@
 //! This module implements common functionalities for OS's strings.
 
-use crate::sys::{Error, OsStr, OsString};
+use crate::{
+    sys::{OsStr, OsString},
+    Error,
+};
 use core::{fmt, ops::Deref};
 
 #[cfg(feature = "std")]
@@ -9,11 +12,11 @@ use std::{
     path::{Path, PathBuf},
 };
 
-impl Clone for OsString {
-    fn clone(&self) -> Self {
-        self.to_os_str()
-            .and_then(|str| str.into_os_string())
-            .expect("Allocation error")
+impl OsString {
+    /// Copies this string into a new allocation. Fails instead of panicking if
+    /// allocation fails, and so `Clone` is not implemented.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        self.to_os_str().and_then(|str| str.into_os_string())
     }
 }
 
@@ -64,7 +67,8 @@ impl Deref for EitherOsStr<'_> {
 }
 
 /// Conversion of anything into an owned OS-native string. If allocation fails,
-/// an error shall be returned.
+/// an error shall be returned. If the string contains a nul-byte in a place
+/// other than the end, [`Error::InvalidPath`] shall be returned.
 pub trait IntoOsString {
     /// Converts with possible allocation error.
     fn into_os_string(self) -> Result<OsString, Error>;
@@ -134,7 +138,9 @@ impl ToOsStr for String {
 }
 
 /// Conversion of anything to an either borrowed or owned OS-native string. If
-/// allocation fails, an error shall be returned.
+/// allocation fails, an error shall be returned. If the string contains a
+/// nul-byte in a place other than the end, [`Error::InvalidPath`] shall be
+/// returned.
 pub trait ToOsStr {
     /// Converts with possible allocation error.
     fn to_os_str<'str>(&'str self) -> Result<EitherOsStr<'str>, Error>;
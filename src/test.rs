@@ -1,4 +1,5 @@
-use crate::{Error, LockFile};
+use crate::{Error, LockFile, LockMode};
+#[cfg(feature = "std")]
 use core::str;
 
 #[cfg(feature = "std")]
@@ -13,7 +14,7 @@ fn read_pid() -> Result<(), Error> {
     let content_a = read_to_string(path)?;
     let content_b = read_to_string(path)?;
 
-    assert!(content_a.trim().len() > 0);
+    assert!(!content_a.trim().is_empty());
     assert!(content_a.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     assert_eq!(content_a, content_b);
@@ -32,7 +33,7 @@ fn try_read_pid() -> Result<(), Error> {
     let content_a = read_to_string(path)?;
     let content_b = read_to_string(path)?;
 
-    assert!(content_a.trim().len() > 0);
+    assert!(!content_a.trim().is_empty());
     assert!(content_a.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     assert_eq!(content_a, content_b);
@@ -43,6 +44,23 @@ fn try_read_pid() -> Result<(), Error> {
 fn check_try_lock_example(
     lockpath: &str,
     expected: &[u8],
+) -> Result<(), Error> {
+    check_example("try_lock", lockpath, expected)
+}
+
+#[cfg(feature = "std")]
+fn check_try_lock_shared_example(
+    lockpath: &str,
+    expected: &[u8],
+) -> Result<(), Error> {
+    check_example("try_lock_shared", lockpath, expected)
+}
+
+#[cfg(feature = "std")]
+fn check_example(
+    example: &str,
+    lockpath: &str,
+    expected: &[u8],
 ) -> Result<(), Error> {
     use std::process::{Command, Stdio};
 
@@ -50,7 +68,7 @@ fn check_try_lock_example(
         .arg("run")
         .arg("-q")
         .arg("--example")
-        .arg("try_lock")
+        .arg(example)
         .arg("--")
         .arg(lockpath)
         .stdout(Stdio::piped())
@@ -64,6 +82,7 @@ fn check_try_lock_example(
     Ok(())
 }
 
+#[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 enum TryPidExpectedRes<'pid> {
     Success { pid_to_differ: &'pid str },
@@ -93,7 +112,7 @@ fn check_try_lock_with_pid_example(
     match expected {
         TryPidExpectedRes::Success { pid_to_differ: pid } => {
             let output = str::from_utf8(&output.stdout).unwrap();
-            assert!(output.trim().len() > 0);
+            assert!(!output.trim().is_empty());
             assert!(output.trim().chars().all(|ch| ch.is_ascii_digit()));
             assert_ne!(output.trim(), pid);
         },
@@ -128,18 +147,18 @@ fn other_process_pid() -> Result<(), Error> {
     assert!(file.try_lock_with_pid()?);
 
     let content = read_to_string(path)?;
-    assert!(content.trim().len() > 0);
+    assert!(!content.trim().is_empty());
     assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     check_try_lock_example(path, b"FAILURE\n")?;
     let content_again = read_to_string(path)?;
-    assert!(content_again.trim().len() > 0);
+    assert!(!content_again.trim().is_empty());
     assert!(content_again.trim().chars().all(|ch| ch.is_ascii_digit()));
     file.unlock()?;
     check_try_lock_example(path, b"SUCCESS\n")?;
 
     let child_content = read_to_string(path)?;
-    assert!(child_content.trim().len() == 0);
+    assert!(child_content.trim().is_empty());
 
     assert!(file.try_lock_with_pid()?);
 
@@ -148,7 +167,7 @@ fn other_process_pid() -> Result<(), Error> {
 
     check_try_lock_with_pid_example(path, TryPidExpectedRes::Failure)?;
     let content_again = read_to_string(path)?;
-    assert!(content_again.trim().len() > 0);
+    assert!(!content_again.trim().is_empty());
     assert!(content_again.trim().chars().all(|ch| ch.is_ascii_digit()));
     file.unlock()?;
     check_try_lock_with_pid_example(
@@ -157,7 +176,7 @@ fn other_process_pid() -> Result<(), Error> {
     )?;
 
     let child_content = read_to_string(path)?;
-    assert!(child_content.trim().len() == 0);
+    assert!(child_content.trim().is_empty());
 
     Ok(())
 }
@@ -181,6 +200,2375 @@ fn other_process_but_curr_reads() -> Result<(), Error> {
     Ok(())
 }
 
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn other_process_shared() -> Result<(), Error> {
+    let path = "testfiles/other_process_shared.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock_shared()?;
+    assert!(other.try_lock_shared()?);
+    assert_eq!(file.lock_mode(), Some(LockMode::Shared));
+    assert_eq!(other.lock_mode(), Some(LockMode::Shared));
+
+    check_try_lock_shared_example(path, b"SUCCESS\n")?;
+    check_try_lock_example(path, b"FAILURE\n")?;
+
+    file.unlock()?;
+    check_try_lock_shared_example(path, b"SUCCESS\n")?;
+    check_try_lock_example(path, b"FAILURE\n")?;
+
+    other.unlock()?;
+    check_try_lock_example(path, b"SUCCESS\n")?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn other_process_exclusive_blocks_shared() -> Result<(), Error> {
+    let path = "testfiles/other_process_exclusive_blocks_shared.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    check_try_lock_shared_example(path, b"FAILURE\n")?;
+    file.unlock()?;
+    assert_eq!(file.lock_mode(), None);
+    check_try_lock_shared_example(path, b"SUCCESS\n")?;
+    Ok(())
+}
+
+#[test]
+fn shared_and_exclusive_handles() -> Result<(), Error> {
+    let path = "testfiles/shared_and_exclusive_handles.lock";
+    let mut writer = LockFile::open(path)?;
+    let mut reader = LockFile::open(path)?;
+    writer.lock_with_pid()?;
+    assert!(!reader.try_lock_shared()?);
+    writer.unlock()?;
+    assert!(reader.try_lock_shared()?);
+    assert_eq!(reader.lock_mode(), Some(LockMode::Shared));
+    assert!(!writer.try_lock()?);
+    assert_eq!(writer.lock_mode(), None);
+    reader.unlock()?;
+    assert!(writer.try_lock()?);
+    assert_eq!(writer.lock_mode(), Some(LockMode::Exclusive));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn convert_keeps_pid() -> Result<(), Error> {
+    use std::fs::read_to_string;
+
+    let path = "testfiles/convert_keeps_pid.lock";
+    let mut file = LockFile::open(path)?;
+    let mut reader = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    let content = read_to_string(path)?;
+    assert!(!content.trim().is_empty());
+
+    file.downgrade()?;
+    assert_eq!(file.lock_mode(), Some(LockMode::Shared));
+    assert!(reader.try_lock_shared()?);
+    assert_eq!(read_to_string(path)?, content);
+    reader.unlock()?;
+    assert_eq!(read_to_string(path)?, content);
+
+    file.upgrade()?;
+    assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    assert!(!reader.try_lock_shared()?);
+    assert_eq!(read_to_string(path)?, content);
+
+    file.unlock()?;
+    assert!(read_to_string(path)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn try_upgrade() -> Result<(), Error> {
+    let path = "testfiles/try_upgrade.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock_shared()?;
+    assert!(file.try_upgrade()?);
+    assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    assert!(!other.try_lock_shared()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn failed_try_upgrade_lets_other_process_in() -> Result<(), Error> {
+    let path = "testfiles/failed_try_upgrade_lets_other_process_in.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock_shared()?;
+    other.lock_shared()?;
+
+    // The other reader conflicts with the exclusive lock, and with the current
+    // backends the shared lock is released before the exclusive one is
+    // requested.
+    assert!(!file.try_upgrade()?);
+    assert_eq!(file.lock_mode(), None);
+
+    check_try_lock_shared_example(path, b"SUCCESS\n")?;
+    other.unlock()?;
+    // Nobody holds the lock anymore: another process can sneak in.
+    check_try_lock_example(path, b"SUCCESS\n")?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_timeout_expires() -> Result<(), Error> {
+    use std::time::{Duration, Instant};
+
+    let path = "testfiles/lock_timeout_expires.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+
+    let start = Instant::now();
+    assert!(!other.lock_timeout(Duration::from_millis(100))?);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(!other.owns_lock());
+
+    assert!(!other.lock_shared_until(Instant::now())?);
+    assert!(!other.owns_lock());
+
+    file.unlock()?;
+    assert!(other.lock_until(Instant::now())?);
+    assert!(other.owns_lock());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_timeout_acquires() -> Result<(), Error> {
+    use std::{fs::read_to_string, thread, time::Duration};
+
+    let path = "testfiles/lock_timeout_acquires.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+
+    let unlocker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        file.unlock()
+    });
+    assert!(other.lock_with_pid_timeout(Duration::from_secs(10))?);
+    unlocker.join().unwrap()?;
+
+    let content = read_to_string(path)?;
+    assert!(!content.trim().is_empty());
+    assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
+    Ok(())
+}
+
+#[test]
+fn guard_unlocks_on_drop() -> Result<(), Error> {
+    let path = "testfiles/guard_unlocks_on_drop.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+
+    fn critical_section(file: &mut LockFile) -> Result<(), Error> {
+        let guard = file.lock_guard()?;
+        assert_eq!(guard.lock_mode(), Some(LockMode::Exclusive));
+        Err(Error::from_raw_os_error(0))
+    }
+
+    assert!(critical_section(&mut file).is_err());
+    assert!(!file.owns_lock());
+
+    let guard = other.try_lock_guard()?.unwrap();
+    assert!(file.try_lock_guard()?.is_none());
+    assert!(file.try_lock_shared_guard()?.is_none());
+    guard.unlock()?;
+    assert!(!other.owns_lock());
+
+    let guard = file.lock_shared_guard()?;
+    assert!(other.try_lock_shared_guard()?.is_some());
+    assert!(!other.owns_lock());
+    drop(guard);
+    assert!(!file.owns_lock());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn guard_with_pid() -> Result<(), Error> {
+    use std::fs::read_to_string;
+
+    let path = "testfiles/guard_with_pid.lock";
+    let mut file = LockFile::open(path)?;
+    {
+        let _guard = file.lock_with_pid_guard()?;
+        let content = read_to_string(path)?;
+        assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
+    }
+    assert!(read_to_string(path)?.is_empty());
+
+    let guard = file.try_lock_with_pid_guard()?.unwrap();
+    assert!(!read_to_string(path)?.trim().is_empty());
+    guard.unlock()?;
+    assert!(read_to_string(path)?.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn owned_guard_across_threads() -> Result<(), Error> {
+    use crate::OwnedLockGuard;
+    use std::thread;
+
+    fn assert_send<T: Send>() {}
+    assert_send::<OwnedLockGuard>();
+
+    let path = "testfiles/owned_guard_across_threads.lock";
+    let guard = LockFile::open(path)?.into_locked()?;
+    let mut other = LockFile::open(path)?;
+    assert!(!other.try_lock()?);
+
+    let file = thread::spawn(move || {
+        assert_eq!(guard.lock_mode(), Some(LockMode::Exclusive));
+        guard.into_inner()
+    })
+    .join()
+    .unwrap()?;
+    assert!(!file.owns_lock());
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    let guard = file.into_locked_shared()?;
+    assert!(other.try_lock_shared()?);
+    other.unlock()?;
+    drop(guard);
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn owned_guard_as_fd() -> Result<(), Error> {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    let path = "testfiles/owned_guard_as_fd.lock";
+    let file = LockFile::open(path)?;
+    let raw = file.as_raw_fd();
+    let guard = file.into_locked_with_pid()?;
+    assert_eq!(guard.as_raw_fd(), raw);
+    assert_eq!(guard.as_fd().as_raw_fd(), raw);
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+fn tokio_runtime() -> Result<tokio::runtime::Runtime, Error> {
+    Ok(tokio::runtime::Builder::new_current_thread().enable_time().build()?)
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn lock_async_does_not_block_runtime() -> Result<(), Error> {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering::SeqCst},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    let path = "testfiles/lock_async_does_not_block_runtime.lock";
+    let mut holder = LockFile::open(path)?;
+    let mut file = LockFile::open(path)?;
+    holder.lock()?;
+
+    let unlocker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        holder.unlock()
+    });
+
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let runtime = tokio_runtime()?;
+    runtime.block_on(async {
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    ticks.fetch_add(1, SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        });
+        let guard = file.lock_async().await?;
+        assert_eq!(guard.lock_mode(), Some(LockMode::Exclusive));
+        ticker.abort();
+        guard.unlock()
+    })?;
+    unlocker.join().unwrap()?;
+
+    assert!(ticks.load(SeqCst) > 1);
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn lock_async_cancelled() -> Result<(), Error> {
+    use std::time::Duration;
+
+    let path = "testfiles/lock_async_cancelled.lock";
+    let mut holder = LockFile::open(path)?;
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    holder.lock_shared()?;
+
+    let runtime = tokio_runtime()?;
+    runtime.block_on(async {
+        let timeout = Duration::from_millis(100);
+        let result = tokio::time::timeout(timeout, file.lock_async()).await;
+        assert!(result.is_err());
+    });
+    assert!(!file.owns_lock());
+    assert!(other.try_lock_shared()?);
+    other.unlock()?;
+    holder.unlock()?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    runtime.block_on(async {
+        let guard = file.lock_shared_async().await?;
+        assert_eq!(guard.lock_mode(), Some(LockMode::Shared));
+        drop(guard);
+        let _guard = file.lock_with_pid_async().await?;
+        assert!(!other.try_lock_shared()?);
+        Ok(())
+    })
+}
+
+/// Minimal executor parking the current thread until woken.
+#[cfg(feature = "async")]
+fn block_on<F>(future: F) -> F::Output
+where
+    F: std::future::Future,
+{
+    use std::{
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut ctx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+            break output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_immediate() -> Result<(), Error> {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    let path = "testfiles/lock_future_immediate.lock";
+    let mut other = LockFile::open(path)?;
+    let mut future = pin!(LockFile::open(path)?.lock_shared_future());
+    let mut ctx = Context::from_waker(Waker::noop());
+    let guard = match future.as_mut().poll(&mut ctx) {
+        Poll::Ready(result) => result?,
+        Poll::Pending => panic!("Unlocked file should be locked on first poll"),
+    };
+    assert_eq!(guard.lock_mode(), Some(LockMode::Shared));
+    assert!(!other.try_lock()?);
+    drop(guard);
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_already_locked() -> Result<(), Error> {
+    let mut file = LockFile::open("testfiles/lock_future_already_locked.lock")?;
+    file.lock()?;
+    let result = block_on(file.lock_future());
+    assert!(matches!(result, Err(Error::AlreadyLocked)));
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_waits() -> Result<(), Error> {
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_future_waits.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+    let unlocker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        holder.unlock()
+    });
+
+    let guard = block_on(LockFile::open(path)?.lock_future())?;
+    unlocker.join().unwrap()?;
+    assert_eq!(guard.lock_mode(), Some(LockMode::Exclusive));
+    let mut other = LockFile::open(path)?;
+    assert!(!other.try_lock_shared()?);
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_timeout() -> Result<(), Error> {
+    use std::time::{Duration, Instant};
+
+    let path = "testfiles/lock_future_timeout.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock_shared()?;
+
+    let start = Instant::now();
+    let future =
+        LockFile::open(path)?.lock_future().timeout(Duration::from_millis(100));
+    assert!(block_on(future)?.is_none());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    let future = LockFile::open(path)?
+        .lock_shared_future()
+        .timeout(Duration::from_millis(100));
+    assert!(block_on(future)?.is_some());
+
+    holder.unlock()?;
+    let future =
+        LockFile::open(path)?.lock_future().timeout(Duration::from_secs(10));
+    assert!(block_on(future)?.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_huge_timeout() -> Result<(), Error> {
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_future_huge_timeout.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+
+    let future = LockFile::open(path)?.lock_future().timeout(Duration::MAX);
+    let unlocker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        holder.unlock()
+    });
+    assert!(block_on(future)?.is_some());
+    unlocker.join().expect("Unlocker thread panicked")?;
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lock_future_cancelled() -> Result<(), Error> {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Waker},
+        thread,
+        time::Duration,
+    };
+
+    let path = "testfiles/lock_future_cancelled.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+
+    {
+        let mut future = pin!(LockFile::open(path)?.lock_future());
+        let mut ctx = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut ctx).is_pending());
+        thread::sleep(Duration::from_millis(50));
+    }
+    holder.unlock()?;
+
+    // The helper thread must give up instead of grabbing the lock.
+    thread::sleep(Duration::from_millis(200));
+    let mut other = LockFile::open(path)?;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn other_process_reports_owner() -> Result<(), Error> {
+    let path = "testfiles/other_process_reports_owner.lock";
+    let mut file = LockFile::open(path)?;
+    check_example("who_locks", path, b"UNLOCKED\n")?;
+
+    file.lock()?;
+    check_example("who_locks", path, b"locked by unknown owner\n")?;
+    file.unlock()?;
+
+    file.lock_with_pid()?;
+    let expected = format!("locked by PID {} (alive)\n", std::process::id());
+    check_example("who_locks", path, expected.as_bytes())?;
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn dead_owner_pid() -> Result<(), Error> {
+    use std::{fs::write, process::Command};
+
+    let mut child = Command::new("cargo").arg("--version").spawn()?;
+    let child_pid = child.id();
+    child.wait()?;
+
+    let path = "testfiles/dead_owner_pid.lock";
+    let file = LockFile::open(path)?;
+    write(path, format!("{}\n", child_pid))?;
+    assert_eq!(file.read_owner_pid()?, Some(child_pid as crate::Pid));
+    assert_eq!(crate::owner_pid(path)?, Some(child_pid as crate::Pid));
+    assert!(!file.is_owner_alive()?);
+    assert!(!crate::is_pid_alive(child_pid as crate::Pid)?);
+
+    write(path, "not a pid\n")?;
+    assert_eq!(file.read_owner_pid()?, None);
+    assert!(!file.is_owner_alive()?);
+
+    write(path, "")?;
+    assert_eq!(file.read_owner_pid()?, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn break_stale_max_age() -> Result<(), Error> {
+    use crate::StalePolicy;
+    use std::{fs::read_to_string, thread, time::Duration};
+
+    let path = "testfiles/break_stale_max_age.lock";
+    let mut stale = LockFile::open(path)?;
+    stale.lock_with_pid()?;
+    thread::sleep(Duration::from_millis(10));
+
+    let mut file = LockFile::open(path)?;
+    let policy = StalePolicy::never().max_age(Duration::ZERO);
+    assert!(file.lock_or_break_stale(policy)?);
+    assert!(file.owns_lock());
+    assert!(stale.owns_lock());
+
+    stale.unlock()?;
+    let info = file.read_owner_info()?.unwrap();
+    assert_eq!(info.pid, std::process::id() as crate::Pid);
+    assert!(info.acquired_at.is_some());
+    assert!(read_to_string(path)?.starts_with("pid="));
+    file.unlock()?;
+
+    assert!(!file.lock_or_break_stale(policy)?);
+    file.unlock()?;
+    #[cfg(unix)]
+    {
+        use std::path::Path;
+
+        assert!(!Path::new("testfiles/break_stale_max_age.lock.break").exists());
+        assert!(
+            !Path::new("testfiles/break_stale_max_age.lock.break.new").exists()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn break_stale_lock_file_backends() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions, StalePolicy};
+    use std::{path::Path, thread, time::Duration};
+
+    let cases = [
+        (Backend::Sentinel, "testfiles/break_stale_sentinel.lock"),
+        (Backend::Mkdir, "testfiles/break_stale_mkdir.lock"),
+    ];
+    for &(backend, path) in cases.iter() {
+        let mut options = LockFileOptions::new();
+        options.backend(backend);
+        let mut stale = options.open(path)?;
+        stale.lock_with_pid()?;
+        thread::sleep(Duration::from_millis(10));
+
+        let mut file = options.open(path)?;
+        let policy = StalePolicy::never().max_age(Duration::ZERO);
+        assert!(file.lock_or_break_stale(policy)?);
+        let info = file.read_owner_info()?.unwrap();
+        assert!(info.acquired_at.is_some());
+
+        // The stale owner leaves the new lock in place.
+        stale.unlock()?;
+        assert!(Path::new(path).exists());
+        let mut other = options.open(path)?;
+        assert!(!other.try_lock()?);
+        assert_eq!(other.read_owner_info()?, Some(info));
+        file.unlock()?;
+        assert!(!Path::new(path).exists());
+
+        assert!(!file.lock_or_break_stale(StalePolicy::never())?);
+        file.unlock()?;
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn break_stale_nofollow() -> Result<(), Error> {
+    use crate::{LockFileOptions, StalePolicy};
+    use std::{fs, os::unix::fs::symlink, path::Path};
+
+    let path = "testfiles/break_stale_nofollow.lock";
+    let break_path = "testfiles/break_stale_nofollow.lock.break";
+    let target = "testfiles/break_stale_nofollow.target";
+    let _ = fs::remove_file(break_path);
+    let _ = fs::remove_file(target);
+    symlink("break_stale_nofollow.target", break_path)?;
+
+    let mut file = LockFileOptions::new().nofollow(true).open(path)?;
+    let result = file.lock_or_break_stale(StalePolicy::never());
+    fs::remove_file(break_path)?;
+
+    assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ELOOP));
+    assert!(!file.owns_lock());
+    assert!(!Path::new(target).exists());
+    assert!(!file.lock_or_break_stale(StalePolicy::never())?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn break_stale_dead_owner() -> Result<(), Error> {
+    use crate::StalePolicy;
+    use std::{
+        fs::{remove_file, write},
+        process::{Command, Stdio},
+    };
+
+    let mut child = Command::new("cargo").arg("--version").spawn()?;
+    let child_pid = child.id();
+    child.wait()?;
+
+    let path = "testfiles/break_stale_dead_owner.lock";
+    let log_path = "testfiles/break_stale_dead_owner.log";
+    let _ = remove_file(log_path);
+
+    // Simulates a lock held on behalf of a dead process.
+    let mut stale = LockFile::open(path)?;
+    stale.lock()?;
+    write(path, format!("{}\n", child_pid))?;
+
+    let mut file = LockFile::open(path)?;
+    assert!(!file.try_lock()?);
+    assert!(!file.is_owner_alive()?);
+
+    let contenders = (0 .. 4)
+        .map(|_| {
+            Command::new("cargo")
+                .arg("run")
+                .arg("-q")
+                .arg("--example")
+                .arg("break_stale")
+                .arg("--")
+                .arg(path)
+                .arg(log_path)
+                .stdout(Stdio::piped())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut outputs = Vec::new();
+    for contender in contenders {
+        let output = contender.wait_with_output()?;
+        assert!(output.status.success());
+        outputs.push(String::from_utf8(output.stdout).unwrap());
+    }
+
+    let broke = outputs.iter().filter(|output| *output == "broke\n").count();
+    let waited = outputs.iter().filter(|output| *output == "waited\n").count();
+    assert_eq!((broke, waited), (1, 3));
+
+    let log = std::fs::read_to_string(log_path)?;
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 8);
+    for pair in lines.chunks(2) {
+        let pid = pair[0].strip_prefix("start ").unwrap();
+        assert_eq!(pair[1].strip_prefix("end "), Some(pid));
+    }
+
+    assert!(stale.owns_lock());
+    stale.unlock()?;
+    assert!(!file.lock_or_break_stale(StalePolicy::dead_owner())?);
+    file.unlock()?;
+    remove_file(log_path)?;
+    Ok(())
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn reused_owner_pid() -> Result<(), Error> {
+    use crate::owner::is_identity_alive_in;
+    use std::{
+        fs::{create_dir_all, remove_dir_all, write},
+        process::Command,
+    };
+
+    let mut child = Command::new("cargo").arg("--version").spawn()?;
+    let child_pid = child.id() as crate::Pid;
+    child.wait()?;
+
+    let path = "testfiles/reused_owner_pid.lock";
+    let file = LockFile::open(path)?;
+    write(path, format!("pid={}\nstart_time=1234\n", child_pid))?;
+    assert_eq!(file.read_owner_pid()?, Some(child_pid));
+    assert!(!file.is_owner_alive()?);
+    let info = file.read_owner_info()?.unwrap();
+    assert_eq!(info.start_time, Some(1234));
+    assert!(!info.is_alive()?);
+
+    // Simulates another process reusing the PID.
+    let procfs = "testfiles/reused_owner_pid_proc";
+    let _ = remove_dir_all(procfs);
+    let stat_dir = format!("{}/{}", procfs, child_pid);
+    create_dir_all(&stat_dir)?;
+    let stat = |start_time: u64| {
+        format!(
+            "{} (cargo (new)) S {}{}\n",
+            child_pid,
+            "0 ".repeat(18),
+            start_time
+        )
+    };
+    write(format!("{}/stat", stat_dir), stat(5678))?;
+    assert!(!is_identity_alive_in(procfs.as_bytes(), child_pid, 1234)?);
+    write(format!("{}/stat", stat_dir), stat(1234))?;
+    assert!(is_identity_alive_in(procfs.as_bytes(), child_pid, 1234)?);
+    remove_dir_all(&stat_dir)?;
+    assert!(!is_identity_alive_in(procfs.as_bytes(), child_pid, 1234)?);
+    // Simulates a live process hidden from the procfs with `hidepid`.
+    let pid = std::process::id() as crate::Pid;
+    assert!(is_identity_alive_in(procfs.as_bytes(), pid, 1234)?);
+
+    let mut file = file;
+    file.lock_with_owner_info(None)?;
+    assert!(file.is_owner_alive()?);
+    assert!(file.read_owner_info()?.unwrap().is_alive()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn owner_info_round_trip() -> Result<(), Error> {
+    use std::{
+        env,
+        fs::read_to_string,
+        time::{Duration, SystemTime},
+    };
+
+    let path = "testfiles/owner_info_round_trip.lock";
+    let mut file = LockFile::open(path)?;
+    let before = SystemTime::now() - Duration::from_secs(1);
+    file.lock_with_owner_info(Some("first\nsecond"))?;
+
+    let content = read_to_string(path)?;
+    assert!(content.starts_with(&format!("pid={}\n", std::process::id())));
+    assert!(content.lines().all(|line| line.contains('=')));
+
+    let info = file.read_owner_info()?.unwrap();
+    assert_eq!(crate::owner_info(path)?.as_ref(), Some(&info));
+    assert_eq!(info.pid, std::process::id() as crate::Pid);
+    assert_eq!(info.tag.as_deref(), Some("first\u{fffd}second"));
+    let acquired_at = info.acquired_at.unwrap();
+    assert!(acquired_at >= before && acquired_at <= SystemTime::now());
+    #[cfg(target_os = "linux")]
+    {
+        assert!(info.hostname.is_some());
+        assert!(info.start_time.is_some());
+        let exe = env::current_exe()?;
+        assert_eq!(info.exe.as_deref(), exe.to_str());
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = env::current_exe;
+
+    assert_eq!(file.read_owner_pid()?, Some(info.pid));
+    assert!(file.is_owner_alive()?);
+
+    file.unlock()?;
+    assert_eq!(file.read_owner_info()?, None);
+    assert!(file.try_lock_with_owner_info(None)?);
+    assert_eq!(file.read_owner_info()?.unwrap().tag, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn owner_info_parse() {
+    use crate::LockOwnerInfo;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let info = LockOwnerInfo::parse(
+        b"pid=7\nfuture_key=whatever\nstart_time=99\nacquired_at=10\n\
+          exe=/bin/a=b\nnot an entry\n",
+    )
+    .unwrap();
+    assert_eq!(info.pid, 7);
+    assert_eq!(info.start_time, Some(99));
+    assert_eq!(info.acquired_at, Some(UNIX_EPOCH + Duration::from_secs(10)));
+    assert_eq!(info.exe.as_deref(), Some("/bin/a=b"));
+    assert_eq!(info.hostname, None);
+
+    assert_eq!(LockOwnerInfo::parse(b" 12 \n").unwrap().pid, 12);
+    assert_eq!(LockOwnerInfo::parse(b"hostname=a\npid=7\n"), None);
+    assert_eq!(LockOwnerInfo::parse(b"pid=x\n"), None);
+    assert_eq!(LockOwnerInfo::parse(b""), None);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn open_ofd(path: &str) -> Result<LockFile, Error> {
+    use crate::{Backend, LockFileOptions};
+
+    LockFileOptions::new().backend(Backend::Ofd).open(path)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn ofd_handles_exclude_each_other() -> Result<(), Error> {
+    let path = "testfiles/ofd_handles_exclude_each_other.lock";
+    let mut file0 = open_ofd(path)?;
+    let mut file1 = open_ofd(path)?;
+
+    file0.lock()?;
+    assert!(!file1.try_lock()?);
+    assert!(!file1.try_lock_shared()?);
+    file0.unlock()?;
+    assert!(file1.try_lock()?);
+    assert!(!file0.try_lock()?);
+    file1.unlock()?;
+
+    file0.lock_shared()?;
+    assert!(file1.try_lock_shared()?);
+    assert!(!file0.try_upgrade()?);
+    assert_eq!(file0.lock_mode(), Some(LockMode::Shared));
+    file1.unlock()?;
+    assert!(file0.try_upgrade()?);
+    assert!(!file1.try_lock_shared()?);
+    file0.downgrade()?;
+    assert!(file1.try_lock_shared()?);
+    file1.unlock()?;
+    file0.unlock()?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn ofd_conflicts_with_ranges() -> Result<(), Error> {
+    let path = "testfiles/ofd_conflicts_with_ranges.lock";
+    let mut file0 = open_ofd(path)?;
+    let mut file1 = LockFile::open(path)?;
+
+    file0.lock_with_pid()?;
+    assert!(file1.try_lock()?);
+    file1.unlock()?;
+    assert!(!file1.try_lock_range(1 << 20, 1, LockMode::Shared)?);
+    file0.unlock()?;
+    assert!(file1.try_lock_range(1 << 20, 1, LockMode::Shared)?);
+    assert!(!file0.try_lock()?);
+    assert!(file0.try_lock_shared()?);
+    file0.unlock()?;
+    file1.unlock_range(1 << 20, 1)?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[test]
+fn ofd_unavailable() {
+    use crate::{Backend, LockFileOptions};
+
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Ofd);
+    assert!(!Backend::Ofd.is_available());
+    assert!(options.open("testfiles/ofd_unavailable.lock").is_err());
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+#[test]
+fn range_exclusion() -> Result<(), Error> {
+    let path = "testfiles/range_exclusion.lock";
+    let mut file0 = LockFile::open(path)?;
+    let mut file1 = LockFile::open(path)?;
+
+    file0.lock_range(0, 10, LockMode::Exclusive)?;
+    assert!(!file1.try_lock_range(0, 10, LockMode::Exclusive)?);
+    assert!(!file1.try_lock_range(9, 1, LockMode::Shared)?);
+    assert!(file1.try_lock_range(10, 10, LockMode::Exclusive)?);
+    assert!(!file0.try_lock_range(15, 1, LockMode::Shared)?);
+    file0.unlock_range(0, 10)?;
+    assert!(file1.try_lock_range(0, 10, LockMode::Shared)?);
+    file1.unlock_range(0, 10)?;
+    file1.unlock_range(10, 10)?;
+
+    file0.lock_range(0, 10, LockMode::Shared)?;
+    assert!(file1.try_lock_range(5, 10, LockMode::Shared)?);
+    assert!(file1.try_lock_range(20, 1, LockMode::Shared)?);
+    file1.unlock_range(20, 1)?;
+    assert!(!file0.try_lock_range(10, 1, LockMode::Exclusive)?);
+    file1.unlock_range(5, 10)?;
+    assert!(file0.try_lock_range(10, 1, LockMode::Exclusive)?);
+    file0.unlock_range(10, 1)?;
+    file0.unlock_range(0, 10)?;
+
+    file0.lock_range(100, 0, LockMode::Exclusive)?;
+    assert!(!file1.try_lock_range(1 << 40, 1, LockMode::Shared)?);
+    assert!(file1.try_lock_range(0, 100, LockMode::Exclusive)?);
+    file0.unlock_range(100, 0)?;
+    file1.unlock_range(0, 100)?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+#[test]
+fn range_independent_from_whole_file() -> Result<(), Error> {
+    let path = "testfiles/range_independent_from_whole_file.lock";
+    let mut file0 = LockFile::open(path)?;
+    let mut file1 = LockFile::open(path)?;
+
+    file0.lock()?;
+    assert!(file1.try_lock_range(0, 4096, LockMode::Exclusive)?);
+    assert!(!file1.owns_lock());
+    assert!(!file1.try_lock()?);
+    file1.unlock_range(0, 4096)?;
+    file0.unlock()?;
+
+    file0.lock_range(0, 4096, LockMode::Exclusive)?;
+    assert!(file1.try_lock()?);
+    file1.unlock()?;
+    file0.unlock_range(0, 4096)?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn range_merge_and_split() -> Result<(), Error> {
+    let path = "testfiles/range_merge_and_split.lock";
+    let mut file0 = LockFile::open(path)?;
+    let mut file1 = LockFile::open(path)?;
+
+    // Adjacent regions merge, so unlocking across them works.
+    file0.lock_range(0, 10, LockMode::Exclusive)?;
+    file0.lock_range(10, 10, LockMode::Exclusive)?;
+    file0.unlock_range(5, 10)?;
+    assert!(file1.try_lock_range(5, 10, LockMode::Exclusive)?);
+    file1.unlock_range(5, 10)?;
+    assert!(!file1.try_lock_range(0, 5, LockMode::Shared)?);
+    assert!(!file1.try_lock_range(15, 5, LockMode::Shared)?);
+
+    // Locking over a held region converts the overlap.
+    file0.lock_range(0, 20, LockMode::Shared)?;
+    assert!(file1.try_lock_range(0, 20, LockMode::Shared)?);
+    file1.unlock_range(0, 20)?;
+    file0.lock_range(10, 10, LockMode::Exclusive)?;
+    assert!(file1.try_lock_range(0, 10, LockMode::Shared)?);
+    assert!(!file1.try_lock_range(10, 1, LockMode::Shared)?);
+    file1.unlock_range(0, 10)?;
+
+    // Unlocking a region which is not locked does nothing.
+    file0.unlock_range(100, 10)?;
+    file0.unlock_range(0, 0)?;
+    assert!(file1.try_lock_range(0, 0, LockMode::Exclusive)?);
+    file1.unlock_range(0, 0)?;
+    Ok(())
+}
+
+#[test]
+fn backend_capabilities() {
+    use crate::{Backend, Capabilities};
+
+    let native = Backend::Native.capabilities();
+    assert!(native.shared && native.ranges && native.per_handle);
+    assert!(!native.nfs_safe);
+
+    let sentinel = Backend::Sentinel.capabilities();
+    assert!(!sentinel.shared && !sentinel.ranges);
+    assert!(sentinel.per_handle && sentinel.nfs_safe);
+    assert_eq!(Backend::Mkdir.capabilities(), sentinel);
+
+    if Backend::Posix.is_available() {
+        let posix = Backend::Posix.capabilities();
+        assert!(posix.shared && posix.ranges && posix.nfs_safe);
+        assert!(!posix.per_handle);
+    } else {
+        assert_eq!(Backend::Posix.capabilities(), Capabilities::default());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn posix_handles_share_process_lock() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions};
+
+    let path = "testfiles/posix_handles_share_process_lock.lock";
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Posix);
+    let mut file0 = options.open(path)?;
+    let mut file1 = options.open(path)?;
+
+    file0.lock()?;
+    assert!(file1.try_lock()?);
+    file1.unlock()?;
+    file0.unlock()?;
+
+    file0.lock_shared()?;
+    file0.upgrade()?;
+    assert_eq!(file0.lock_mode(), Some(LockMode::Exclusive));
+    file0.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sentinel_backend() -> Result<(), Error> {
+    use crate::{sys, Backend, LockFileOptions};
+    use std::{fs, path::Path};
+
+    let path = "testfiles/sentinel_backend.lock";
+    let _ = fs::remove_file(path);
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Sentinel);
+    let mut file0 = options.open(path)?;
+    let mut file1 = options.open(path)?;
+    assert!(!Path::new(path).exists());
+
+    file0.lock_with_pid()?;
+    assert!(Path::new(path).is_file());
+    assert!(!file1.try_lock()?);
+    assert_eq!(file1.read_owner_pid()?, Some(sys::pid()));
+    file0.unlock()?;
+    assert!(!Path::new(path).exists());
+    assert_eq!(file1.read_owner_pid()?, None);
+
+    assert!(file1.try_lock()?);
+    assert!(file0.try_lock_shared().is_err());
+    assert!(!file0.owns_lock());
+    drop(file1);
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sentinel_records_owner() -> Result<(), Error> {
+    use crate::{sys, Backend, LockFileOptions};
+    use std::fs;
+
+    let path = "testfiles/sentinel_records_owner.lock";
+    let _ = fs::remove_file(path);
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Sentinel);
+    let mut file0 = options.open(path)?;
+    let file1 = options.open(path)?;
+
+    file0.lock()?;
+    let info = file1.read_owner_info()?.unwrap();
+    assert_eq!(info.pid, sys::pid());
+    assert!(info.acquired_at.is_some());
+    assert!(file1.is_owner_alive()?);
+    file0.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn sentinel_left_behind_by_crash() -> Result<(), Error> {
+    use crate::{sys, Backend, LockFileOptions};
+    use std::{fs, path::Path};
+
+    let path = "testfiles/sentinel_left_behind_by_crash.lock";
+    let _ = fs::remove_file(path);
+    check_example("sentinel_crash", path, b"LOCKED\n")?;
+    assert!(Path::new(path).is_file());
+
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Sentinel);
+    let mut file = options.open(path)?;
+    assert!(!file.try_lock()?);
+    let info = file.read_owner_info()?.unwrap();
+    assert_ne!(info.pid, sys::pid());
+    assert!(!info.is_alive()?);
+
+    fs::remove_file(path)?;
+    assert!(file.try_lock()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn mkdir_backend() -> Result<(), Error> {
+    use crate::{sys, Backend, LockFileOptions};
+    use std::{fs, path::Path, thread, time::Duration};
+
+    let path = "testfiles/mkdir_backend.lock";
+    let _ = fs::remove_file(format!("{}/owner", path));
+    let _ = fs::remove_dir(path);
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Mkdir);
+    let mut file0 = options.open(path)?;
+    let mut file1 = options.open(path)?;
+
+    file0.lock_with_pid()?;
+    assert!(Path::new(path).is_dir());
+    assert!(!file1.try_lock()?);
+    assert_eq!(file1.read_owner_pid()?, Some(sys::pid()));
+
+    let waiter = thread::spawn(move || -> Result<LockFile, Error> {
+        file1.lock()?;
+        Ok(file1)
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!waiter.is_finished());
+    file0.unlock()?;
+    let mut file1 = waiter.join().unwrap()?;
+    assert!(file1.owns_lock());
+    file1.unlock()?;
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn mkdir_backend_mode() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions};
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let path = "testfiles/mkdir_backend_mode.lock";
+    let _ = fs::remove_file(format!("{}/owner", path));
+    let _ = fs::remove_dir(path);
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Mkdir)
+        .mode(0o600)
+        .open(path)?;
+    file.lock()?;
+    let dir_mode = fs::metadata(path)?.permissions().mode();
+    let owner_mode =
+        fs::metadata(format!("{}/owner", path))?.permissions().mode();
+    assert_eq!(dir_mode & 0o777, 0o700);
+    assert_eq!(owner_mode & 0o777, 0o600);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_dir_owner_and_timeout() -> Result<(), Error> {
+    use crate::{sys, LockDir};
+    use std::{fs, path::Path, time::Duration};
+
+    let path = "testfiles/lock_dir_owner_and_timeout.lock";
+    let _ = fs::remove_dir_all(path);
+    let mut holder = LockDir::open(path)?;
+    let mut waiter = LockDir::open(path)?;
+
+    holder.lock()?;
+    assert!(Path::new(path).join("owner").is_file());
+    assert_eq!(waiter.read_owner_pid()?, Some(sys::pid()));
+    assert!(waiter.is_owner_alive()?);
+    assert!(!waiter.lock_timeout(Duration::from_millis(50))?);
+    assert!(!waiter.owns_lock());
+
+    drop(holder);
+    assert!(!Path::new(path).exists());
+    assert_eq!(waiter.read_owner_pid()?, None);
+    assert!(waiter.lock_timeout(Duration::from_millis(50))?);
+    waiter.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn lock_dir_contention() -> Result<(), Error> {
+    use crate::LockDir;
+    use std::{
+        env,
+        fs::{self, OpenOptions},
+        io::Write,
+        process::{self, Command, Stdio},
+        thread,
+        time::Duration,
+    };
+
+    let temp_dir = env::temp_dir()
+        .join(format!("fslock-lock_dir_contention-{}", process::id()));
+    let _ = fs::remove_dir_all(&temp_dir);
+    fs::create_dir_all(&temp_dir)?;
+    let path = temp_dir.join("contended.lock");
+    let log_path = temp_dir.join("contended.log");
+
+    let processes = (0 .. 4)
+        .map(|_| {
+            Command::new("cargo")
+                .arg("run")
+                .arg("-q")
+                .arg("--example")
+                .arg("lock_dir")
+                .arg("--")
+                .arg(&path)
+                .arg(&log_path)
+                .stdout(Stdio::piped())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let threads = (0 .. 8)
+        .map(|index| {
+            let path = path.clone();
+            let log_path = log_path.clone();
+            thread::spawn(move || -> Result<(), Error> {
+                let mut lockdir = LockDir::open(&path)?;
+                for _ in 0 .. 3 {
+                    lockdir.lock()?;
+                    let mut log = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&log_path)?;
+                    writeln!(log, "start thread-{}", index)?;
+                    thread::sleep(Duration::from_millis(5));
+                    writeln!(log, "end thread-{}", index)?;
+                    lockdir.unlock()?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    for child in processes {
+        let output = child.wait_with_output()?;
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"DONE\n");
+    }
+
+    let log = fs::read_to_string(&log_path)?;
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2 * (4 + 8 * 3));
+    for pair in lines.chunks(2) {
+        let owner = pair[0].strip_prefix("start ").unwrap();
+        assert_eq!(pair[1].strip_prefix("end "), Some(owner));
+    }
+    assert!(!path.exists());
+
+    fs::remove_dir_all(&temp_dir)?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn delete_on_unlock() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::path::Path;
+
+    let path = "testfiles/delete_on_unlock.lock";
+    let mut options = LockFileOptions::new();
+    options.delete_on_unlock(true);
+    let mut file0 = options.open(path)?;
+    let mut file1 = options.open(path)?;
+
+    file0.lock_with_pid()?;
+    assert!(Path::new(path).exists());
+    file0.unlock()?;
+    assert!(!Path::new(path).exists());
+
+    // The other handle's file was deleted, so it locks a new one.
+    assert!(file1.try_lock()?);
+    assert!(Path::new(path).exists());
+    let mut file2 = options.open(path)?;
+    assert!(!file2.try_lock()?);
+    file1.unlock()?;
+
+    // The last shared lock deletes the file.
+    file0.lock_shared()?;
+    file1.lock_shared()?;
+    file0.unlock()?;
+    assert!(Path::new(path).exists());
+    file1.unlock()?;
+    assert!(!Path::new(path).exists());
+
+    file2.lock()?;
+    drop(file2);
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn delete_on_unlock_contention() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{
+        fs,
+        io::Write,
+        path::Path,
+        process::{Command, Stdio},
+        thread,
+    };
+
+    let path = "testfiles/delete_on_unlock_contention.lock";
+    let log_path = "testfiles/delete_on_unlock_contention.log";
+    let _ = fs::remove_file(log_path);
+
+    let processes = (0 .. 4)
+        .map(|_| {
+            Command::new("cargo")
+                .arg("run")
+                .arg("-q")
+                .arg("--example")
+                .arg("delete_on_unlock")
+                .arg("--")
+                .arg(path)
+                .arg(log_path)
+                .arg("25")
+                .stdout(Stdio::piped())
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let threads = (0 .. 4)
+        .map(|index| {
+            thread::spawn(move || -> Result<(), Error> {
+                let mut options = LockFileOptions::new();
+                options.delete_on_unlock(true);
+                for _ in 0 .. 25 {
+                    let mut file = options.open(path)?;
+                    file.lock()?;
+                    let mut log = fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(log_path)?;
+                    writeln!(log, "start thread-{}", index)?;
+                    writeln!(log, "end thread-{}", index)?;
+                    file.unlock()?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    for child in processes {
+        let output = child.wait_with_output()?;
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"DONE\n");
+    }
+
+    let log = fs::read_to_string(log_path)?;
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2 * 8 * 25);
+    for pair in lines.chunks(2) {
+        let owner = pair[0].strip_prefix("start ").unwrap();
+        assert_eq!(pair[1].strip_prefix("end "), Some(owner));
+    }
+    assert!(!Path::new(path).exists());
+
+    fs::remove_file(log_path)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn custom_backend() -> Result<(), Error> {
+    use crate::{
+        Backend,
+        Capabilities,
+        LockBackend,
+        LockFileOptions,
+        LockTarget,
+    };
+    use std::sync::Mutex;
+
+    /// Records the operations asked to the native backend.
+    #[derive(Debug)]
+    struct Recording(Mutex<Vec<&'static str>>);
+
+    impl Recording {
+        fn record(&self, operation: &'static str) {
+            self.0.lock().unwrap().push(operation);
+        }
+    }
+
+    impl LockBackend for Recording {
+        fn capabilities(&self) -> Capabilities {
+            Backend::Native.capabilities()
+        }
+
+        fn lock(
+            &self,
+            file: &mut LockTarget,
+            mode: LockMode,
+        ) -> Result<(), Error> {
+            self.record("lock");
+            Backend::Native.implementation().lock(file, mode)
+        }
+
+        fn try_lock(
+            &self,
+            file: &mut LockTarget,
+            mode: LockMode,
+        ) -> Result<bool, Error> {
+            self.record("try_lock");
+            Backend::Native.implementation().try_lock(file, mode)
+        }
+
+        fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+            self.record("unlock");
+            Backend::Native.implementation().unlock(file)
+        }
+
+        fn close(&self, file: &mut LockTarget) {
+            self.record("close");
+            Backend::Native.implementation().close(file)
+        }
+    }
+
+    static RECORDING: Recording = Recording(Mutex::new(Vec::new()));
+
+    let path = "testfiles/custom_backend_test.lock";
+    let backend = Backend::Custom(&RECORDING);
+    let mut file = LockFileOptions::new().backend(backend).open(path)?;
+    assert_eq!(file.backend(), backend);
+    assert_ne!(backend, Backend::Native);
+    file.lock_shared()?;
+    file.upgrade()?;
+    assert!(file.lock_range(0, 1, LockMode::Shared).is_err());
+    drop(file);
+
+    assert_eq!(
+        *RECORDING.0.lock().unwrap(),
+        ["lock", "unlock", "lock", "unlock", "close"]
+    );
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_file_options_create() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let path = "testfiles/lock_file_options_create.lock";
+    let _ = fs::remove_file(path);
+
+    assert!(LockFileOptions::new().create(false).open(path).is_err());
+
+    let mut file =
+        LockFileOptions::new().create_new(true).mode(0o600).open(path)?;
+    assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o600);
+    assert!(LockFileOptions::new().create_new(true).open(path).is_err());
+
+    let mut other = LockFileOptions::new().create(false).open(path)?;
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_file_options_nofollow() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::symlink};
+
+    let path = "testfiles/lock_file_options_nofollow.lock";
+    let link = "testfiles/lock_file_options_nofollow.link";
+    let _ = fs::remove_file(link);
+    symlink("lock_file_options_nofollow.lock", link)?;
+
+    let result = LockFileOptions::new().nofollow(true).open(link);
+    let followed = LockFile::open(link);
+    fs::remove_file(link)?;
+
+    assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ELOOP));
+    followed?;
+    assert!(LockFileOptions::new().nofollow(true).open(path).is_ok());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_file_options_cloexec() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::os::fd::AsRawFd;
+
+    let path = "testfiles/lock_file_options_cloexec.lock";
+    let cloexec = |file: &LockFile| {
+        let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFD) };
+        assert!(flags >= 0);
+        flags & libc::FD_CLOEXEC != 0
+    };
+
+    assert!(cloexec(&LockFile::open(path)?));
+    assert!(!cloexec(&LockFileOptions::new().cloexec(false).open(path)?));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_file_options_read_only() -> Result<(), Error> {
+    use crate::LockFileOptions;
+
+    let path = "testfiles/lock_file_options_read_only.lock";
+    let mut writer = LockFile::open(path)?;
+    writer.lock_with_pid()?;
+    writer.unlock()?;
+
+    let mut reader0 = LockFileOptions::new().read_only(true).open(path)?;
+    let mut reader1 = LockFileOptions::new().read_only(true).open(path)?;
+    reader0.lock_shared()?;
+    assert!(reader1.try_lock_shared()?);
+    assert!(!writer.try_lock()?);
+    reader0.unlock()?;
+    reader1.unlock()?;
+
+    assert!(reader0.lock_with_pid().is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_file_options_truncate_on_unlock() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, process};
+
+    let path = "testfiles/lock_file_options_truncate_on_unlock.lock";
+    let mut file =
+        LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+
+    file.lock_with_pid()?;
+    file.unlock()?;
+    let content = fs::read_to_string(path)?;
+    assert_eq!(content.trim().parse::<u32>().ok(), Some(process::id()));
+
+    let mut file = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    file.unlock()?;
+    assert_eq!(fs::metadata(path)?.len(), 0);
+    Ok(())
+}
+
+#[test]
+fn read_into_write_all() -> Result<(), Error> {
+    use crate::LockFileOptions;
+
+    let path = "testfiles/read_into_write_all.lock";
+    let mut file =
+        LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+    let mut buf = [0; 16];
+
+    file.lock()?;
+    file.write_all(b"generation ")?;
+    file.write_all(b"7")?;
+    file.unlock()?;
+
+    let mut other = LockFile::open(path)?;
+    other.lock_shared()?;
+    let len = other.read_into(&mut buf)?;
+    assert_eq!(&buf[.. len], b"generation 7");
+    assert_eq!(other.read_into(&mut buf)?, 0);
+    other.unlock()?;
+
+    other.lock()?;
+    other.unlock()?;
+    file.lock_shared()?;
+    assert_eq!(file.read_into(&mut buf)?, 0);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_write_seek() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{
+        fs,
+        io::{Read, Seek, SeekFrom, Write},
+    };
+
+    let path = "testfiles/read_write_seek.lock";
+    let mut file =
+        LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+    let mut content = String::new();
+
+    let mut guard = file.lock_guard()?;
+    guard.write_all(b"{\"generation\": 1}")?;
+    guard.seek(SeekFrom::Start(15))?;
+    write!(guard, "2}}")?;
+    guard.rewind()?;
+    guard.read_to_string(&mut content)?;
+    assert_eq!(content, "{\"generation\": 2}");
+    drop(guard);
+    assert_eq!(fs::read_to_string(path)?, content);
+
+    let mut guard = file.into_locked()?;
+    assert_eq!(guard.seek(SeekFrom::End(-2))?, 15);
+    content.clear();
+    guard.read_to_string(&mut content)?;
+    assert_eq!(content, "2}");
+    guard.unlock()?;
+    Ok(())
+}
+
+#[test]
+fn guard_content() -> Result<(), Error> {
+    use crate::LockFileOptions;
+
+    let path = "testfiles/guard_content.lock";
+    // Erases the content left by previous runs.
+    LockFile::open(path)?.lock_guard()?.unlock()?;
+    let mut file =
+        LockFileOptions::new().truncate_on_unlock(false).open(path)?;
+    let mut buf = [0; 16];
+
+    file.lock_guard()?.write_all(b"borrowed")?;
+    let mut guard = LockFile::open(path)?.into_locked_shared()?;
+    let len = guard.read_into(&mut buf)?;
+    assert_eq!(&buf[.. len], b"borrowed");
+    drop(guard);
+
+    let mut guard = file.into_locked()?;
+    guard.write_all(b" owned")?;
+    guard.unlock()?;
+    let mut other = LockFile::open(path)?;
+    let len = other.lock_shared_guard()?.read_into(&mut buf)?;
+    assert_eq!(&buf[.. len], b"borrowed owned");
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn from_file() -> Result<(), Error> {
+    use crate::StalePolicy;
+    use std::fs;
+
+    let path = "testfiles/from_file.lock";
+    fs::write(path, "data")?;
+    let data = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut file = LockFile::from_file(data);
+    let mut other = LockFile::open(path)?;
+
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    assert_eq!(fs::read_to_string(path)?, "data");
+
+    other.lock()?;
+    assert!(!file.try_lock_shared()?);
+    other.unlock()?;
+
+    let result = file.lock_or_break_stale(StalePolicy::dead_owner());
+    assert!(result.is_err());
+    assert!(!file.owns_lock());
+
+    // Unlike the wrapped file, the other handle truncates on unlock.
+    let data = file.into_file()?;
+    assert_eq!(data.metadata()?.len(), 0);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn from_owned_fd() -> Result<(), Error> {
+    use std::{
+        fs::File,
+        os::fd::{FromRawFd, IntoRawFd, OwnedFd},
+    };
+
+    let path = "testfiles/from_owned_fd.lock";
+    let mut file = LockFile::from(OwnedFd::from(File::create(path)?));
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+
+    let fd = file.into_raw_fd();
+    let mut file = unsafe { LockFile::from_raw_fd(fd) };
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn into_file_while_locked() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions};
+
+    let path = "testfiles/into_file_while_locked.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    assert!(matches!(file.into_file(), Err(Error::AlreadyLocked)));
+    assert!(LockFile::open(path)?.try_lock()?);
+
+    let path = "testfiles/into_file_sentinel.lock";
+    let file = LockFileOptions::new().backend(Backend::Sentinel).open(path)?;
+    assert!(file.into_file().is_err());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn into_raw_fd_while_locked() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions};
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    let path = "testfiles/into_raw_fd_while_locked.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    let fd = file.into_raw_fd();
+    assert!(LockFile::open(path)?.try_lock()?);
+    drop(unsafe { LockFile::from_raw_fd(fd) });
+
+    let path = "testfiles/into_raw_fd_sentinel.lock";
+    let mut file =
+        LockFileOptions::new().backend(Backend::Sentinel).open(path)?;
+    file.lock()?;
+    assert_eq!(file.into_raw_fd(), -1);
+    let mut other =
+        LockFileOptions::new().backend(Backend::Sentinel).open(path)?;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn durable_writes() -> Result<(), Error> {
+    use crate::{
+        Backend,
+        Capabilities,
+        LockBackend,
+        LockFileOptions,
+        LockTarget,
+    };
+    use std::{
+        fs,
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering::SeqCst},
+            Mutex,
+        },
+    };
+
+    /// Records the operations asked to the native backend, failing syncs on
+    /// demand.
+    #[derive(Debug)]
+    struct Syncing {
+        operations: Mutex<Vec<&'static str>>,
+        fail_sync: AtomicBool,
+    }
+
+    impl Syncing {
+        fn record(&self, operation: &'static str) {
+            self.operations.lock().unwrap().push(operation);
+        }
+
+        fn take(&self) -> Vec<&'static str> {
+            std::mem::take(&mut *self.operations.lock().unwrap())
+        }
+    }
+
+    impl LockBackend for Syncing {
+        fn capabilities(&self) -> Capabilities {
+            Backend::Native.capabilities()
+        }
+
+        fn lock(
+            &self,
+            file: &mut LockTarget,
+            mode: LockMode,
+        ) -> Result<(), Error> {
+            self.record("lock");
+            Backend::Native.implementation().lock(file, mode)
+        }
+
+        fn try_lock(
+            &self,
+            file: &mut LockTarget,
+            mode: LockMode,
+        ) -> Result<bool, Error> {
+            self.record("try_lock");
+            Backend::Native.implementation().try_lock(file, mode)
+        }
+
+        fn unlock(&self, file: &mut LockTarget) -> Result<(), Error> {
+            self.record("unlock");
+            Backend::Native.implementation().unlock(file)
+        }
+
+        fn sync(&self, file: &mut LockTarget) -> Result<(), Error> {
+            self.record("sync");
+            if self.fail_sync.load(SeqCst) {
+                return Err(io::Error::from(io::ErrorKind::Other).into());
+            }
+            Backend::Native.implementation().sync(file)
+        }
+    }
+
+    static SYNCING: Syncing = Syncing {
+        operations: Mutex::new(Vec::new()),
+        fail_sync: AtomicBool::new(false),
+    };
+
+    let path = "testfiles/durable_writes.lock";
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Custom(&SYNCING)).durable(true);
+    let mut file = options.open(path)?;
+
+    file.lock_with_pid()?;
+    assert!(!fs::read_to_string(path)?.is_empty());
+    file.unlock()?;
+    assert_eq!(SYNCING.take(), ["lock", "sync", "unlock"]);
+
+    SYNCING.fail_sync.store(true, SeqCst);
+    let error = file.lock_with_pid().unwrap_err();
+    assert!(matches!(error, Error::Io(_)));
+    assert!(!file.owns_lock());
+    assert_eq!(SYNCING.take(), ["lock", "sync", "unlock"]);
+
+    // Sync errors are ignored unless durable.
+    let mut file = options.durable(false).open(path)?;
+    assert!(file.try_lock_with_owner_info(Some("forensics"))?);
+    assert!(file.owns_lock());
+    file.unlock()?;
+    assert_eq!(SYNCING.take(), ["try_lock", "sync", "unlock"]);
+    SYNCING.fail_sync.store(false, SeqCst);
+
+    // Write errors are always reported.
+    let mut file = options.read_only(true).open(path)?;
+    assert!(file.lock_with_pid().is_err());
+    assert!(!file.owns_lock());
+    assert_eq!(SYNCING.take(), ["lock", "unlock"]);
+
+    let sentinel = "testfiles/durable_writes_sentinel.lock";
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Sentinel)
+        .durable(true)
+        .open(sentinel)?;
+    file.lock_with_pid()?;
+    assert!(!fs::read_to_string(sentinel)?.is_empty());
+    file.unlock()?;
+    Ok(())
+}
+
+#[test]
+fn error_classification() -> Result<(), Error> {
+    use crate::{Backend, LockFileOptions};
+
+    let mut options = LockFileOptions::new();
+    options.backend(Backend::Mkdir);
+    let mut file = options.open("testfiles/error_dir.lock")?;
+    assert!(matches!(file.lock_shared(), Err(Error::NotSupported)));
+    assert!(!file.owns_lock());
+
+    #[cfg(unix)]
+    {
+        assert!(matches!(
+            Error::from_raw_os_error(libc::EWOULDBLOCK),
+            Error::WouldBlock
+        ));
+        assert!(matches!(
+            Error::from_raw_os_error(libc::EDEADLK),
+            Error::Deadlock
+        ));
+        assert!(matches!(
+            Error::from_raw_os_error(libc::EINTR),
+            Error::Interrupted
+        ));
+        let error = Error::from_raw_os_error(libc::ENOENT);
+        assert!(matches!(error, Error::Os(libc::ENOENT)));
+        assert_eq!(error.raw_os_error(), Some(libc::ENOENT));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn error_into_io_error() -> Result<(), Error> {
+    use std::io;
+
+    let error = io::Error::from(Error::Deadlock);
+    assert_eq!(error.kind(), io::ErrorKind::Other);
+    assert!(matches!(Error::from(error), Error::Deadlock));
+
+    let error = io::Error::from(Error::WouldBlock);
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert!(matches!(Error::from(error), Error::WouldBlock));
+
+    let error = io::Error::from(Error::NotLocked);
+    assert_eq!(error.to_string(), "handle does not own a lock");
+    assert!(matches!(Error::from(error), Error::NotLocked));
+
+    let error = io::Error::from(Error::Os(2));
+    assert_eq!(error.raw_os_error(), Some(2));
+    assert_eq!(Error::from(error).raw_os_error(), Some(2));
+
+    let error = Error::from(io::Error::other("custom"));
+    assert!(matches!(error, Error::Io(_)));
+    assert_eq!(error.to_string(), "custom");
+    Ok(())
+}
+
+#[test]
+fn lock_misuse() -> Result<(), Error> {
+    let path = "testfiles/lock_misuse.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+
+    assert!(matches!(file.unlock(), Err(Error::NotLocked)));
+    assert!(matches!(file.upgrade(), Err(Error::NotLocked)));
+    assert!(matches!(file.downgrade(), Err(Error::NotLocked)));
+    file.lock()?;
+    assert!(matches!(file.lock(), Err(Error::AlreadyLocked)));
+    assert!(matches!(file.lock_shared(), Err(Error::AlreadyLocked)));
+    assert!(matches!(file.try_lock(), Err(Error::AlreadyLocked)));
+    assert!(matches!(file.try_lock_with_pid(), Err(Error::AlreadyLocked)));
+    assert!(matches!(file.upgrade(), Err(Error::NotLocked)));
+    assert!(!other.try_lock_shared()?);
+    assert_eq!(file.lock_mode(), Some(LockMode::Exclusive));
+    file.unlock()?;
+    assert!(matches!(file.unlock(), Err(Error::NotLocked)));
+
+    assert!(other.try_lock_shared()?);
+    assert!(matches!(other.downgrade(), Err(Error::NotLocked)));
+    assert_eq!(other.lock_mode(), Some(LockMode::Shared));
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_misuse_variants() -> Result<(), Error> {
+    use crate::{CancelToken, StalePolicy};
+    use std::time::Duration;
+
+    let path = "testfiles/lock_misuse_variants.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    let token = CancelToken::new();
+    assert!(matches!(
+        file.lock_timeout(Duration::ZERO),
+        Err(Error::AlreadyLocked)
+    ));
+    assert!(matches!(file.lock_cancellable(&token), Err(Error::AlreadyLocked)));
+    assert!(matches!(
+        file.lock_or_break_stale(StalePolicy::never()),
+        Err(Error::AlreadyLocked)
+    ));
+    assert!(matches!(file.lock_guard(), Err(Error::AlreadyLocked)));
+    assert!(matches!(file.try_lock_guard(), Err(Error::AlreadyLocked)));
+    assert!(matches!(
+        file.lock_with_owner_info(None),
+        Err(Error::AlreadyLocked)
+    ));
+    assert!(file.owns_lock());
+    assert!(matches!(file.into_locked(), Err(Error::AlreadyLocked)));
+    assert!(LockFile::open(path)?.try_lock()?);
+    Ok(())
+}
+
+#[test]
+fn invalid_path() {
+    use crate::{LockFileOptions, ToOsStr};
+
+    assert!(matches!("in\0valid".to_os_str(), Err(Error::InvalidPath)));
+    assert!(matches!(LockFile::open("in\0valid"), Err(Error::InvalidPath)));
+    assert!(matches!(
+        LockFileOptions::new().open("testfiles/in\0valid.lock"),
+        Err(Error::InvalidPath)
+    ));
+    assert!("testfiles/valid.lock\0".to_os_str().is_ok());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn invalid_path_std() {
+    use crate::IntoOsString;
+    use std::{ffi::OsString, path::PathBuf};
+
+    let path = PathBuf::from("in\0valid");
+    assert!(matches!(LockFile::open(&path), Err(Error::InvalidPath)));
+    assert!(matches!(
+        IntoOsString::into_os_string(path),
+        Err(Error::InvalidPath)
+    ));
+    let string = OsString::from("in\0valid");
+    assert!(matches!(
+        IntoOsString::into_os_string(string),
+        Err(Error::InvalidPath)
+    ));
+    let string = String::from("in\0valid");
+    assert!(matches!(LockFile::open(&string), Err(Error::InvalidPath)));
+}
+
+/// Installs a handler for `SIGUSR1` which does nothing, without
+/// `SA_RESTART`, so the signal interrupts blocking calls instead of killing
+/// the process.
+#[cfg(all(unix, feature = "std"))]
+fn handle_sigusr1() {
+    extern "C" fn on_signal(_: libc::c_int) {}
+
+    unsafe {
+        let mut action: libc::sigaction = core::mem::zeroed();
+        action.sa_sigaction = on_signal as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGUSR1, &action, core::ptr::null_mut());
+    }
+}
+
+/// Locks the given file from another thread, with the given backend and
+/// interrupt policy, returning whether it ended up owning the lock.
+#[cfg(all(unix, feature = "std"))]
+fn spawn_interrupted_locker(
+    path: &'static str,
+    backend: crate::Backend,
+    policy: crate::InterruptPolicy,
+) -> std::thread::JoinHandle<Result<bool, Error>> {
+    use crate::LockFileOptions;
+
+    std::thread::spawn(move || {
+        let mut file = LockFileOptions::new()
+            .backend(backend)
+            .interrupt_policy(policy)
+            .open(path)?;
+        file.lock()?;
+        Ok(file.owns_lock())
+    })
+}
+
+/// Sends `SIGUSR1` to the given thread the given number of times, or until it
+/// finishes if no number is given.
+#[cfg(all(unix, feature = "std"))]
+fn signal_thread<T>(handle: &std::thread::JoinHandle<T>, times: Option<u32>) {
+    use std::{os::unix::thread::JoinHandleExt, thread, time::Duration};
+
+    let mut sent = 0;
+    while times.map_or(!handle.is_finished(), |times| sent < times) {
+        unsafe {
+            libc::pthread_kill(handle.as_pthread_t(), libc::SIGUSR1);
+        }
+        thread::sleep(Duration::from_millis(5));
+        sent += 1;
+    }
+}
+
+/// Backends which block in the kernel, and so can be interrupted by signals.
+#[cfg(all(unix, feature = "std"))]
+fn blocking_backends() -> Vec<crate::Backend> {
+    use crate::Backend;
+
+    [Backend::Native, Backend::Ofd]
+        .iter()
+        .copied()
+        .filter(|backend| backend.is_available())
+        .collect()
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn interrupt_policy_retry() -> Result<(), Error> {
+    use crate::InterruptPolicy;
+
+    let path = "testfiles/interrupt_policy_retry.lock";
+    handle_sigusr1();
+    for backend in blocking_backends() {
+        let mut holder =
+            crate::LockFileOptions::new().backend(backend).open(path)?;
+        holder.lock()?;
+        let handle =
+            spawn_interrupted_locker(path, backend, InterruptPolicy::Retry);
+        signal_thread(&handle, Some(20));
+        assert!(!handle.is_finished());
+        holder.unlock()?;
+        assert!(handle.join().expect("Locker thread panicked")?);
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn interrupt_policy_fail() -> Result<(), Error> {
+    use crate::InterruptPolicy;
+
+    let path = "testfiles/interrupt_policy_fail.lock";
+    handle_sigusr1();
+    for backend in blocking_backends() {
+        let mut holder =
+            crate::LockFileOptions::new().backend(backend).open(path)?;
+        holder.lock()?;
+        let handle =
+            spawn_interrupted_locker(path, backend, InterruptPolicy::Fail);
+        signal_thread(&handle, None);
+        let result = handle.join().expect("Locker thread panicked");
+        assert!(matches!(result, Err(Error::Interrupted)));
+        assert!(holder.owns_lock());
+        holder.unlock()?;
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn interrupt_policy_flag() -> Result<(), Error> {
+    use crate::InterruptPolicy;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+    let path = "testfiles/interrupt_policy_flag.lock";
+    handle_sigusr1();
+    for backend in blocking_backends() {
+        CANCELLED.store(false, Ordering::SeqCst);
+        let mut holder =
+            crate::LockFileOptions::new().backend(backend).open(path)?;
+        holder.lock()?;
+        let policy = InterruptPolicy::Flag(&CANCELLED);
+        let handle = spawn_interrupted_locker(path, backend, policy);
+        signal_thread(&handle, Some(20));
+        assert!(!handle.is_finished());
+        CANCELLED.store(true, Ordering::SeqCst);
+        signal_thread(&handle, None);
+        let result = handle.join().expect("Locker thread panicked");
+        assert!(matches!(result, Err(Error::Interrupted)));
+        holder.unlock()?;
+    }
+    Ok(())
+}
+
+/// A backend wrapping the native one, whose next operations are interrupted as
+/// many times as set.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct InterruptingBackend(std::sync::atomic::AtomicUsize);
+
+#[cfg(feature = "std")]
+impl InterruptingBackend {
+    /// Makes the next given number of operations fail as interrupted.
+    fn interrupt(&self, times: usize) {
+        self.0.store(times, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Fails as interrupted if interruptions are left.
+    fn check(&self) -> Result<(), Error> {
+        use std::sync::atomic::Ordering;
+
+        let left = self.0.load(Ordering::SeqCst);
+        if left == 0 {
+            return Ok(());
+        }
+        self.0.store(left - 1, Ordering::SeqCst);
+        Err(Error::Interrupted)
+    }
+}
+
+#[cfg(feature = "std")]
+impl crate::LockBackend for InterruptingBackend {
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Backend::Native.capabilities()
+    }
+
+    fn lock(
+        &self,
+        file: &mut crate::LockTarget,
+        mode: crate::LockMode,
+    ) -> Result<(), Error> {
+        self.check()?;
+        crate::Backend::Native.implementation().lock(file, mode)
+    }
+
+    fn try_lock(
+        &self,
+        file: &mut crate::LockTarget,
+        mode: crate::LockMode,
+    ) -> Result<bool, Error> {
+        self.check()?;
+        crate::Backend::Native.implementation().try_lock(file, mode)
+    }
+
+    fn unlock(&self, file: &mut crate::LockTarget) -> Result<(), Error> {
+        self.check()?;
+        crate::Backend::Native.implementation().unlock(file)
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn interrupted_unlock() -> Result<(), Error> {
+    use crate::{Backend, InterruptPolicy, LockFileOptions};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    static BACKEND: InterruptingBackend =
+        InterruptingBackend(AtomicUsize::new(0));
+    static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+    let path = "testfiles/interrupted_unlock.lock";
+    let mut other = LockFile::open(path)?;
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Custom(&BACKEND))
+        .interrupt_policy(InterruptPolicy::Fail)
+        .open(path)?;
+    file.lock()?;
+    BACKEND.interrupt(1);
+    assert!(matches!(file.unlock(), Err(Error::Interrupted)));
+    assert!(file.owns_lock());
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    assert!(!file.owns_lock());
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Custom(&BACKEND))
+        .interrupt_policy(InterruptPolicy::Flag(&CANCELLED))
+        .open(path)?;
+    file.lock()?;
+    BACKEND.interrupt(3);
+    file.unlock()?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    file.lock()?;
+    CANCELLED.store(true, Ordering::SeqCst);
+    BACKEND.interrupt(1);
+    assert!(matches!(file.unlock(), Err(Error::Interrupted)));
+    assert!(file.owns_lock());
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn interrupted_try_lock() -> Result<(), Error> {
+    use crate::{Backend, InterruptPolicy, LockFileOptions};
+    use core::sync::atomic::AtomicUsize;
+
+    static BACKEND: InterruptingBackend =
+        InterruptingBackend(AtomicUsize::new(0));
+
+    let path = "testfiles/interrupted_try_lock.lock";
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Custom(&BACKEND))
+        .interrupt_policy(InterruptPolicy::Fail)
+        .open(path)?;
+    BACKEND.interrupt(1);
+    assert!(matches!(file.try_lock(), Err(Error::Interrupted)));
+    assert!(!file.owns_lock());
+    assert!(file.try_lock()?);
+    file.unlock()?;
+
+    let mut file = LockFileOptions::new()
+        .backend(Backend::Custom(&BACKEND))
+        .interrupt_policy(InterruptPolicy::Retry)
+        .open(path)?;
+    BACKEND.interrupt(3);
+    assert!(file.try_lock()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_cancellable() -> Result<(), Error> {
+    use crate::CancelToken;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let path = "testfiles/lock_cancellable.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+
+    let token = CancelToken::new();
+    let waiter = {
+        let token = token.clone();
+        thread::spawn(move || {
+            let mut file = LockFile::open(path)?;
+            let result = file.lock_cancellable(&token);
+            Ok::<_, Error>((result, file.owns_lock()))
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert!(!waiter.is_finished());
+    let then = Instant::now();
+    token.cancel();
+    let (result, owns_lock) = waiter.join().expect("Waiter thread panicked")?;
+    assert!(then.elapsed() < Duration::from_millis(500));
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(!owns_lock);
+    assert!(token.is_cancelled());
+
+    holder.unlock()?;
+    let mut file = LockFile::open(path)?;
+    assert!(matches!(file.lock_cancellable(&token), Err(Error::Cancelled)));
+    assert!(file.try_lock()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_cancellable_acquires() -> Result<(), Error> {
+    use crate::CancelToken;
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_cancellable_acquires.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock_shared()?;
+
+    let token = CancelToken::new();
+    let waiter = {
+        let token = token.clone();
+        thread::spawn(move || {
+            let mut file = LockFile::open(path)?;
+            file.lock_cancellable(&token)?;
+            Ok::<_, Error>(file.lock_mode())
+        })
+    };
+    let mut reader = LockFile::open(path)?;
+    reader.lock_shared_cancellable(&token)?;
+    reader.unlock()?;
+    thread::sleep(Duration::from_millis(50));
+    holder.unlock()?;
+    let mode = waiter.join().expect("Waiter thread panicked")?;
+    assert_eq!(mode, Some(LockMode::Exclusive));
+    assert!(!token.is_cancelled());
+    Ok(())
+}
+
 #[cfg(all(unix, feature = "std"))]
 #[test]
 fn as_fd() -> Result<(), Error> {
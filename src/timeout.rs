@@ -0,0 +1,237 @@
+//! This module implements lock acquisition with timeouts and deadlines.
+
+use crate::{
+    backend::{MAX_POLL_MS, MIN_POLL_MS},
+    Error,
+    LockFile,
+    LockMode,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shortest sleep between two attempts to lock.
+const MIN_BACKOFF: Duration = Duration::from_millis(MIN_POLL_MS as u64);
+
+/// Longest sleep between two attempts to lock.
+const MAX_BACKOFF: Duration = Duration::from_millis(MAX_POLL_MS as u64);
+
+/// Exponential backoff used when polling for a lock.
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    /// How long the next sleep will be.
+    current: Duration,
+}
+
+impl Backoff {
+    /// Creates a backoff starting at the shortest sleep.
+    pub(crate) fn new() -> Self {
+        Self { current: MIN_BACKOFF }
+    }
+
+    /// Computes the next sleep, never past the given deadline, and increases
+    /// the backoff. Returns `None` if the deadline has already passed.
+    pub(crate) fn next_delay(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Option<Duration> {
+        let duration = match deadline {
+            Some(deadline) => {
+                match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if remaining > Duration::ZERO => {
+                        remaining.min(self.current)
+                    },
+                    _ => return None,
+                }
+            },
+            None => self.current,
+        };
+        self.current = (self.current * 2).min(MAX_BACKOFF);
+        Some(duration)
+    }
+
+    /// Sleeps for the current backoff, but never past the given deadline, and
+    /// then increases the backoff. Returns `false` if the deadline has already
+    /// passed, without sleeping.
+    pub(crate) fn sleep_until(&mut self, deadline: Option<Instant>) -> bool {
+        match self.next_delay(deadline) {
+            Some(duration) => {
+                thread::sleep(duration);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl LockFile {
+    /// Locks this file, blocking at most for the given duration while it is
+    /// not possible to lock (i.e. someone else already owns a lock). Returns
+    /// whether the lock was acquired. After locked, if no attempt to unlock is
+    /// made, it will be automatically unlocked on the file handle drop.
+    ///
+    /// Acquisition is done by polling [`LockFile::try_lock`] with an
+    /// exponential backoff, so no lock is left behind when the timeout expires:
+    /// either this call returns `true` and this handle owns the lock, or it
+    /// returns `false` and it does not.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/timeout.lock")?;
+    /// if file.lock_timeout(Duration::from_millis(100))? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_timeout(&mut self, timeout: Duration) -> Result<bool, Error> {
+        self.lock_until(deadline_after(timeout))
+    }
+
+    /// Locks this file, blocking at most until the given deadline while it is
+    /// not possible to lock (i.e. someone else already owns a lock). Returns
+    /// whether the lock was acquired. See [`LockFile::lock_timeout`] for
+    /// details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let deadline = Instant::now() + Duration::from_millis(100);
+    /// let mut file = LockFile::open("testfiles/until.lock")?;
+    /// if file.lock_until(deadline)? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_until(&mut self, deadline: Instant) -> Result<bool, Error> {
+        self.lock_until_with_mode(LockMode::Exclusive, Some(deadline))
+    }
+
+    /// Locks this file in shared mode, blocking at most for the given duration
+    /// while it is not possible to lock (i.e. someone else owns an exclusive
+    /// lock). Returns whether the lock was acquired. See
+    /// [`LockFile::lock_timeout`] for details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_shared_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        self.lock_shared_until(deadline_after(timeout))
+    }
+
+    /// Locks this file in shared mode, blocking at most until the given
+    /// deadline while it is not possible to lock (i.e. someone else owns an
+    /// exclusive lock). Returns whether the lock was acquired. See
+    /// [`LockFile::lock_timeout`] for details.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_shared_until(
+        &mut self,
+        deadline: Instant,
+    ) -> Result<bool, Error> {
+        self.lock_until_with_mode(LockMode::Shared, Some(deadline))
+    }
+
+    /// Locks this file and writes this process's PID into the file, which will
+    /// be erased on unlock. Like [`LockFile::lock_timeout`], blocks at most for
+    /// the given duration and returns whether the lock was acquired.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::{fs::read_to_string, time::Duration};
+    ///
+    /// let mut file = LockFile::open("testfiles/pid_timeout.lock")?;
+    /// if file.lock_with_pid_timeout(Duration::from_millis(100))? {
+    ///     let content = read_to_string("testfiles/pid_timeout.lock")?;
+    ///     assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with_pid_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        self.lock_with_pid_until(deadline_after(timeout))
+    }
+
+    /// Locks this file and writes this process's PID into the file, which will
+    /// be erased on unlock. Like [`LockFile::lock_until`], blocks at most until
+    /// the given deadline and returns whether the lock was acquired.
+    ///
+    /// # Errors
+    /// Fails with [`Error::AlreadyLocked`] if this handle already owns a lock.
+    pub fn lock_with_pid_until(
+        &mut self,
+        deadline: Instant,
+    ) -> Result<bool, Error> {
+        if !self.lock_until(deadline)? {
+            return Ok(false);
+        }
+        self.write_pid().map(|_| true)
+    }
+
+    /// Polls for a lock in the given mode until the given deadline, or forever
+    /// if there is no deadline.
+    pub(crate) fn lock_until_with_mode(
+        &mut self,
+        mode: LockMode,
+        deadline: Option<Instant>,
+    ) -> Result<bool, Error> {
+        self.check_unlocked()?;
+        let mut backoff = Backoff::new();
+        loop {
+            if self.try_acquire(mode)? {
+                return Ok(true);
+            }
+            if !backoff.sleep_until(deadline) {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+/// Computes a deadline from now, saturating far in the future on overflow.
+pub(crate) fn deadline_after(timeout: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_add(timeout)
+        .unwrap_or_else(|| now + Duration::from_secs(60 * 60 * 24 * 365))
+}
@@ -1,6 +1,17 @@
-use crate::{EitherOsStr, IntoOsString, ToOsStr};
+use crate::{
+    EitherOsStr,
+    Error,
+    IntoOsString,
+    LockFileOptions,
+    LockMode,
+    ToOsStr,
+};
 use core::{fmt, mem::transmute, ptr::NonNull, slice, str};
 
+use core::convert::TryFrom;
+
+use core::mem::MaybeUninit;
+
 #[cfg(feature = "std")]
 use std::{ffi, os::unix::ffi::OsStrExt};
 
@@ -43,7 +54,7 @@ fn errno() -> libc::c_int {
 
 #[cfg(feature = "std")]
 fn errno() -> libc::c_int {
-    Error::last_os_error().raw_os_error().unwrap_or(0) as libc::c_int
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0) as libc::c_int
 }
 
 /// A type representing file descriptor on Unix.
@@ -52,44 +63,34 @@ pub type FileDesc = libc::c_int;
 /// A type representing Process ID on Unix.
 pub type Pid = libc::pid_t;
 
-#[cfg(feature = "std")]
-/// An IO error.
-pub type Error = std::io::Error;
-
-#[cfg(not(feature = "std"))]
-#[derive(Debug)]
-/// An IO error. Without std, you can only get a message or an OS error code.
-pub struct Error {
-    code: i32,
+/// Returns the last OS error code of this thread.
+pub fn last_error_code() -> i32 {
+    errno() as i32
 }
 
-#[cfg(not(feature = "std"))]
-impl Error {
-    /// Creates an error from a raw OS error code.
-    pub fn from_raw_os_error(code: i32) -> Self {
-        Self { code }
-    }
-
-    /// Creates an error from the last OS error code.
-    pub fn last_os_error() -> Error {
-        Self::from_raw_os_error(errno() as i32)
-    }
-
-    /// Raw OS error code. Returns option for compatibility with std.
-    pub fn raw_os_error(&self) -> Option<i32> {
-        Some(self.code)
+/// Classifies a raw OS error code into an error.
+pub fn classify_error(code: i32) -> Error {
+    match code {
+        libc::EINTR => Error::Interrupted,
+        libc::ETIMEDOUT => Error::TimedOut,
+        libc::EDEADLK => Error::Deadlock,
+        _ if code == libc::EWOULDBLOCK || code == libc::EAGAIN => {
+            Error::WouldBlock
+        },
+        _ if code == libc::ENOTSUP || code == libc::EOPNOTSUPP => {
+            Error::NotSupported
+        },
+        _ => Error::Os(code),
     }
 }
 
+/// Formats the message of a raw OS error code.
 #[cfg(not(feature = "std"))]
-impl fmt::Display for Error {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let msg_ptr = unsafe { libc::strerror(self.code as libc::c_int) };
-        let len = unsafe { libc::strlen(msg_ptr) };
-        let slice = unsafe { slice::from_raw_parts(msg_ptr, len) };
-        write!(fmt, "{}", unsafe { OsStr::from_slice(slice) })?;
-        Ok(())
-    }
+pub fn fmt_error(code: i32, fmt: &mut fmt::Formatter) -> fmt::Result {
+    let msg_ptr = unsafe { libc::strerror(code as libc::c_int) };
+    let len = unsafe { libc::strlen(msg_ptr) };
+    let slice = unsafe { slice::from_raw_parts(msg_ptr, len) };
+    write!(fmt, "{}", unsafe { OsStr::from_slice(slice) })
 }
 
 /// Owned allocation of an OS-native string.
@@ -131,6 +132,11 @@ impl OsStr {
         transmute(slice)
     }
 
+    /// Returns the empty string.
+    pub(crate) fn empty() -> &'static Self {
+        unsafe { Self::from_slice(&[0]) }
+    }
+
     fn bytes_wo_null(&self) -> &[u8] {
         unsafe {
             slice::from_raw_parts(
@@ -139,6 +145,47 @@ impl OsStr {
             )
         }
     }
+
+    /// Allocates a copy of this string with the given suffix appended.
+    pub fn with_suffix(&self, suffix: &str) -> Result<OsString, Error> {
+        alloc_os_string(self.bytes_wo_null(), suffix.as_bytes())
+    }
+
+    /// Allocates the path of the directory containing this path.
+    fn parent(&self) -> Result<OsString, Error> {
+        let bytes = self.bytes_wo_null();
+        let parent = match bytes.iter().rposition(|&byte| byte == b'/') {
+            Some(0) => &bytes[.. 1],
+            Some(index) => &bytes[.. index],
+            None => b".",
+        };
+        alloc_os_string(parent, b"")
+    }
+}
+
+/// Allocates a nul-terminated string made of the given prefix and suffix,
+/// which must not contain nul-bytes.
+fn alloc_os_string(prefix: &[u8], suffix: &[u8]) -> Result<OsString, Error> {
+    let len = prefix.len() + suffix.len() + 1;
+    let alloc = unsafe { libc::malloc(len) };
+    let alloc = match NonNull::new(alloc as *mut libc::c_char) {
+        Some(alloc) => alloc,
+        None => {
+            return Err(Error::last_os_error());
+        },
+    };
+    unsafe {
+        let ptr = alloc.as_ptr().cast::<u8>();
+        core::ptr::copy_nonoverlapping(prefix.as_ptr(), ptr, prefix.len());
+        core::ptr::copy_nonoverlapping(
+            suffix.as_ptr(),
+            ptr.add(prefix.len()),
+            suffix.len(),
+        );
+        *ptr.add(len - 1) = 0;
+    }
+
+    Ok(OsString { alloc, len })
 }
 
 impl fmt::Debug for OsStr {
@@ -229,7 +276,7 @@ impl ToOsStr for ffi::OsStr {
 fn make_os_str<'a>(slice: &'a [u8]) -> Result<EitherOsStr<'a>, Error> {
     if let Some((&last, init)) = slice.split_last() {
         if init.contains(&0) {
-            panic!("Path to file cannot contain nul-byte in the middle");
+            return Err(Error::InvalidPath);
         }
         if last == 0 {
             let str =
@@ -257,24 +304,164 @@ fn make_os_str<'a>(slice: &'a [u8]) -> Result<EitherOsStr<'a>, Error> {
     Ok(EitherOsStr::Owned(OsString { alloc, len: slice.len() + 1 }))
 }
 
+/// Error reported for operations not supported on the current platform.
+pub fn unsupported() -> Error {
+    Error::NotSupported
+}
+
+/// Returns the error reported when operating on a lock file which is not open.
+pub fn not_open() -> Error {
+    Error::from_raw_os_error(libc::EBADF)
+}
+
 /// Returns the ID of the current process.
 pub fn pid() -> Pid {
     unsafe { libc::getpid() }
 }
 
-/// Opens a file with only purpose of locking it. Creates it if it does not
-/// exist. Path must not contain a nul-byte in the middle, but a nul-byte in the
-/// end (and only in the end) is allowed, which in this case no extra allocation
-/// will be made. Otherwise, an extra allocation is made.
-pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
-    let fd = unsafe {
-        libc::open(
-            path.bytes.as_ptr(),
-            libc::O_RDWR | libc::O_CLOEXEC | libc::O_CREAT,
-            (libc::S_IRUSR | libc::S_IWUSR | libc::S_IRGRP | libc::S_IROTH)
-                as libc::c_int,
+/// Writes the host name into the given buffer, returning its length, or `None`
+/// if it is not available.
+pub fn hostname(buf: &mut [u8]) -> Option<usize> {
+    let res = unsafe {
+        libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len())
+    };
+    if res < 0 {
+        return None;
+    }
+    buf.iter().position(|&byte| byte == 0).filter(|&len| len > 0)
+}
+
+/// Writes the path of the current executable into the given buffer, returning
+/// its length, or `None` if it is not available.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn exe_path(buf: &mut [u8]) -> Option<usize> {
+    let res = unsafe {
+        libc::readlink(
+            b"/proc/self/exe\0".as_ptr() as *const libc::c_char,
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
         )
     };
+    if res <= 0 || res as usize >= buf.len() {
+        None
+    } else {
+        Some(res as usize)
+    }
+}
+
+/// Writes the path of the current executable into the given buffer, returning
+/// its length, or `None` if it is not available.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn exe_path(_buf: &mut [u8]) -> Option<usize> {
+    None
+}
+
+/// Returns the start time of the current process, in clock ticks since boot,
+/// as reported by field 22 of `/proc/self/stat`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn start_time() -> Option<u64> {
+    let path = unsafe {
+        OsStr::from_slice(transmute::<&[u8], &[libc::c_char]>(
+            b"/proc/self/stat\0",
+        ))
+    };
+    let fd = open_read(path).ok()?;
+    let mut buf = [0; 1024];
+    let result = read_at(fd, 0, &mut buf);
+    close(fd);
+    parse_stat_start_time(&buf[.. result.ok()?])
+}
+
+/// Returns the start time of the current process, which is not available on
+/// this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn start_time() -> Option<u64> {
+    None
+}
+
+/// Reads the start time of the process with the given ID, in clock ticks since
+/// boot, from the procfs mounted at the given path. Returns `None` if the
+/// process is not visible there, either because there is no such process or
+/// because it is hidden, e.g. with `hidepid`, so callers must confirm with
+/// [`is_alive`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn process_start_time(
+    procfs: &[u8],
+    pid: Pid,
+) -> Result<Option<u64>, Error> {
+    use core::fmt::Write;
+
+    let mut path_buf = [0; 256];
+    let mut writer = crate::fmt::BufWriter::new(&mut path_buf);
+    let path = str::from_utf8(procfs)
+        .ok()
+        .filter(|_| !procfs.contains(&0))
+        .and_then(|procfs| write!(writer, "{}/{}/stat\0", procfs, pid).ok())
+        .and_then(|_| make_os_str(writer.written()).ok())
+        .ok_or_else(|| Error::from_raw_os_error(libc::EINVAL))?;
+
+    let fd = match open_read(&path) {
+        Ok(fd) => fd,
+        Err(error) => {
+            return match error.raw_os_error() {
+                Some(
+                    libc::ENOENT | libc::ESRCH | libc::EACCES | libc::EPERM,
+                ) => Ok(None),
+                _ => Err(error),
+            };
+        },
+    };
+    let mut buf = [0; 1024];
+    let result = read_at(fd, 0, &mut buf);
+    close(fd);
+    match parse_stat_start_time(&buf[.. result?]) {
+        Some(start_time) => Ok(Some(start_time)),
+        None => Err(Error::from_raw_os_error(libc::EINVAL)),
+    }
+}
+
+/// Parses field 22 (`starttime`) of the content of a `/proc/<pid>/stat` file.
+/// The second field is the executable name between parentheses, which may
+/// itself contain spaces and parentheses, so fields are counted from the last
+/// closing parenthesis.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn parse_stat_start_time(stat: &[u8]) -> Option<u64> {
+    let comm_end = stat.iter().rposition(|&byte| byte == b')')?;
+    let mut fields = stat[comm_end + 1 ..]
+        .split(|byte| byte.is_ascii_whitespace())
+        .filter(|field| !field.is_empty());
+    // Fields after the executable name start at field 3.
+    let field = fields.nth(22 - 3)?;
+    str::from_utf8(field).ok()?.parse().ok()
+}
+
+/// Returns the current time, in seconds since the Unix epoch.
+pub fn now_unix() -> u64 {
+    let now = unsafe { libc::time(core::ptr::null_mut()) };
+    if now < 0 {
+        0
+    } else {
+        now as u64
+    }
+}
+
+/// Opens a file with only purpose of locking it, as configured by the given
+/// options. Path must not contain a nul-byte in the middle, but a nul-byte in
+/// the end (and only in the end) is allowed, which in this case no extra
+/// allocation will be made. Otherwise, an extra allocation is made.
+pub fn open_with(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<FileDesc, Error> {
+    let mut flags =
+        if options.read_only { libc::O_RDONLY } else { libc::O_RDWR };
+    if options.create || options.create_new {
+        flags |= libc::O_CREAT;
+    }
+    if options.create_new {
+        flags |= libc::O_EXCL;
+    }
+    let fd = unsafe { open_raw(path, flags, options) };
 
     if fd >= 0 {
         Ok(fd)
@@ -283,13 +470,301 @@ pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
     }
 }
 
+/// Creates a new file and opens it for reading and writing, using the
+/// permissions and flags of the given options. Returns `None` if the file
+/// already exists.
+pub fn create_new(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<Option<FileDesc>, Error> {
+    let flags = libc::O_RDWR | libc::O_CREAT | libc::O_EXCL;
+    let fd = unsafe { open_raw(path, flags, options) };
+
+    if fd >= 0 {
+        Ok(Some(fd))
+    } else if errno() == libc::EEXIST {
+        Ok(None)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Calls `open` with the given access and creation flags, adding the flags
+/// and permissions of the given options.
+unsafe fn open_raw(
+    path: &OsStr,
+    mut flags: libc::c_int,
+    options: &LockFileOptions,
+) -> libc::c_int {
+    if options.cloexec {
+        flags |= libc::O_CLOEXEC;
+    }
+    if options.nofollow {
+        flags |= libc::O_NOFOLLOW;
+    }
+    libc::open(path.bytes.as_ptr(), flags, options.mode as libc::c_uint)
+}
+
+/// Creates a new directory, using the permissions of the given options, with
+/// search permission added wherever read permission is given. Returns `false`
+/// if it already exists.
+pub fn create_dir(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<bool, Error> {
+    let mode = options.mode | (options.mode & 0o444) >> 2;
+    let res = unsafe { libc::mkdir(path.bytes.as_ptr(), mode as libc::mode_t) };
+    if res >= 0 {
+        Ok(true)
+    } else if errno() == libc::EEXIST {
+        Ok(false)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Removes the empty directory at the given path.
+pub fn remove_dir(path: &OsStr) -> Result<(), Error> {
+    let res = unsafe { libc::rmdir(path.bytes.as_ptr()) };
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Removes the file at the given path. Open handles to it remain valid.
+pub fn remove(path: &OsStr) -> Result<(), Error> {
+    let res = unsafe { libc::unlink(path.bytes.as_ptr()) };
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Syncs the directory containing the given path to disk, so an entry just
+/// created in it survives a crash.
+pub fn sync_parent(path: &OsStr) -> Result<(), Error> {
+    let parent = path.parent()?;
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    let fd = unsafe { libc::open(parent.as_ref().bytes.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    let result = fsync(fd);
+    close(fd);
+    result
+}
+
+/// Sleeps for the given number of milliseconds.
+pub fn sleep_ms(millis: u32) {
+    let mut time = libc::timespec {
+        tv_sec: (millis / 1000) as libc::time_t,
+        tv_nsec: (millis % 1000 * 1_000_000) as libc::c_long,
+    };
+    loop {
+        let mut remaining = time;
+        let res = unsafe { libc::nanosleep(&time, &mut remaining) };
+        if res >= 0 || errno() != libc::EINTR {
+            break;
+        }
+        time = remaining;
+    }
+}
+
+/// Opens an existing file for reading only. Does not create it.
+pub fn open_read(path: &OsStr) -> Result<FileDesc, Error> {
+    let fd = unsafe {
+        libc::open(path.bytes.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC)
+    };
+
+    if fd >= 0 {
+        Ok(fd)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Opens an existing file for reading only, returning `None` if it does not
+/// exist.
+pub fn open_read_existing(path: &OsStr) -> Result<Option<FileDesc>, Error> {
+    match open_read(path) {
+        Ok(fd) => Ok(Some(fd)),
+        Err(error) if error.raw_os_error() == Some(libc::ENOENT) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Reads data from the given open file at the given offset, without moving the
+/// file's cursor. Returns how many bytes were read, which is less than the
+/// buffer's size only at the end of the file.
+pub fn read_at(
+    fd: FileDesc,
+    mut offset: u64,
+    buf: &mut [u8],
+) -> Result<usize, Error> {
+    let mut total = 0;
+    while total < buf.len() {
+        let read = unsafe {
+            libc::pread(
+                fd,
+                buf[total ..].as_mut_ptr() as *mut libc::c_void,
+                buf.len() - total,
+                offset as libc::off_t,
+            )
+        };
+        if read < 0 {
+            if errno() == libc::EINTR {
+                continue;
+            }
+            return Err(Error::last_os_error());
+        }
+        if read == 0 {
+            break;
+        }
+        total += read as usize;
+        offset += read as u64;
+    }
+
+    Ok(total)
+}
+
+/// Reads data from the given open file at the file's cursor, advancing it.
+/// Returns how many bytes were read, which is less than the buffer's size only
+/// at the end of the file.
+pub fn read(fd: FileDesc, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut total = 0;
+    while total < buf.len() {
+        let read = unsafe {
+            libc::read(
+                fd,
+                buf[total ..].as_mut_ptr() as *mut libc::c_void,
+                buf.len() - total,
+            )
+        };
+        if read < 0 {
+            if errno() == libc::EINTR {
+                continue;
+            }
+            return Err(Error::last_os_error());
+        }
+        if read == 0 {
+            break;
+        }
+        total += read as usize;
+    }
+
+    Ok(total)
+}
+
+/// Moves the cursor of the given open file, returning its new position from
+/// the start of the file.
+#[cfg(feature = "std")]
+pub fn seek(fd: FileDesc, position: std::io::SeekFrom) -> Result<u64, Error> {
+    let (offset, whence) = match position {
+        std::io::SeekFrom::Start(offset) => (offset as i64, libc::SEEK_SET),
+        std::io::SeekFrom::Current(offset) => (offset, libc::SEEK_CUR),
+        std::io::SeekFrom::End(offset) => (offset, libc::SEEK_END),
+    };
+    let res = unsafe { libc::lseek(fd, offset as libc::off_t, whence) };
+
+    if res >= 0 {
+        Ok(res as u64)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Returns whether a process with the given ID exists, by sending it the null
+/// signal.
+pub fn is_alive(pid: Pid) -> Result<bool, Error> {
+    if pid <= 0 {
+        return Ok(false);
+    }
+    let res = unsafe { libc::kill(pid, 0) };
+    if res >= 0 {
+        Ok(true)
+    } else {
+        match errno() {
+            // The process exists, but belongs to someone else.
+            libc::EPERM => Ok(true),
+            libc::ESRCH => Ok(false),
+            err => Err(Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+/// Returns whether the given path currently refers to the given open file.
+/// Returns `false` if the path does not exist.
+pub fn same_file(path: &OsStr, fd: FileDesc) -> Result<bool, Error> {
+    let mut path_stat = MaybeUninit::<libc::stat>::uninit();
+    let res =
+        unsafe { libc::stat(path.bytes.as_ptr(), path_stat.as_mut_ptr()) };
+    if res < 0 {
+        return match errno() {
+            libc::ENOENT => Ok(false),
+            err => Err(Error::from_raw_os_error(err)),
+        };
+    }
+    let path_stat = unsafe { path_stat.assume_init() };
+
+    let mut fd_stat = MaybeUninit::<libc::stat>::uninit();
+    let res = unsafe { libc::fstat(fd, fd_stat.as_mut_ptr()) };
+    if res < 0 {
+        return Err(Error::last_os_error());
+    }
+    let fd_stat = unsafe { fd_stat.assume_init() };
+
+    Ok(path_stat.st_dev == fd_stat.st_dev && path_stat.st_ino == fd_stat.st_ino)
+}
+
+#[cfg(feature = "std")]
+/// Returns the last modification time of the given open file, in seconds since
+/// the Unix epoch.
+pub fn modified_unix(fd: FileDesc) -> Result<u64, Error> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let res = unsafe { libc::fstat(fd, stat.as_mut_ptr()) };
+    if res < 0 {
+        return Err(Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    Ok(u64::try_from(stat.st_mtime).unwrap_or(0))
+}
+
+/// Atomically replaces the file at the destination path with the file at the
+/// source path. Open handles to the replaced file remain valid.
+pub fn rename(source: &OsStr, dest: &OsStr) -> Result<(), Error> {
+    let res =
+        unsafe { libc::rename(source.bytes.as_ptr(), dest.bytes.as_ptr()) };
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Renames the file or directory at the given source path, like [`rename`].
+/// Returns `false` if there is nothing at the source path.
+pub fn rename_existing(source: &OsStr, dest: &OsStr) -> Result<bool, Error> {
+    match rename(source, dest) {
+        Ok(()) => Ok(true),
+        Err(error) if error.raw_os_error() == Some(libc::ENOENT) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
 /// Writes data into the given open file.
 pub fn write(fd: FileDesc, mut bytes: &[u8]) -> Result<(), Error> {
     while !bytes.is_empty() {
         let written = unsafe {
             libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len())
         };
-        if written < 0 && errno() != libc::EAGAIN {
+        if written < 0 {
+            let err = errno();
+            if err == libc::EINTR || err == libc::EAGAIN {
+                continue;
+            }
             return Err(Error::last_os_error());
         }
         bytes = &bytes[written as usize ..];
@@ -298,6 +773,7 @@ pub fn write(fd: FileDesc, mut bytes: &[u8]) -> Result<(), Error> {
     Ok(())
 }
 
+/// Syncs the content of the given open file to disk.
 pub fn fsync(fd: FileDesc) -> Result<(), Error> {
     let result = unsafe { libc::fsync(fd) };
 
@@ -324,9 +800,17 @@ pub fn truncate(fd: FileDesc) -> Result<(), Error> {
     }
 }
 
+/// Converts a lock mode into the corresponding `flock` operation.
+fn flock_op(mode: LockMode) -> libc::c_int {
+    match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+    }
+}
+
 /// Tries to lock a file and blocks until it is possible to lock.
-pub fn lock(fd: FileDesc) -> Result<(), Error> {
-    let res = unsafe { libc::flock(fd, libc::LOCK_EX) };
+pub fn lock(fd: FileDesc, mode: LockMode) -> Result<(), Error> {
+    let res = unsafe { libc::flock(fd, flock_op(mode)) };
     if res >= 0 {
         Ok(())
     } else {
@@ -335,13 +819,13 @@ pub fn lock(fd: FileDesc) -> Result<(), Error> {
 }
 
 /// Tries to lock a file but returns as soon as possible if already locked.
-pub fn try_lock(fd: FileDesc) -> Result<bool, Error> {
-    let res = unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) };
+pub fn try_lock(fd: FileDesc, mode: LockMode) -> Result<bool, Error> {
+    let res = unsafe { libc::flock(fd, flock_op(mode) | libc::LOCK_NB) };
     if res >= 0 {
         Ok(true)
     } else {
         let err = errno();
-        if err == libc::EWOULDBLOCK || err == libc::EINTR {
+        if err == libc::EWOULDBLOCK {
             Ok(false)
         } else {
             Err(Error::from_raw_os_error(err as i32))
@@ -359,6 +843,170 @@ pub fn unlock(fd: FileDesc) -> Result<(), Error> {
     }
 }
 
+/// Command setting a byte-range lock, waiting if `wait` is set. On Linux and
+/// Android, open file description locks are used, which are held per handle.
+/// Elsewhere, classic POSIX record locks are used, which are held per process.
+fn range_command(wait: bool) -> libc::c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if wait {
+            libc::F_OFD_SETLKW
+        } else {
+            libc::F_OFD_SETLK
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        posix_command(wait)
+    }
+}
+
+/// Command setting a classic POSIX record lock, held per process, waiting if
+/// `wait` is set.
+fn posix_command(wait: bool) -> libc::c_int {
+    if wait {
+        libc::F_SETLKW
+    } else {
+        libc::F_SETLK
+    }
+}
+
+/// Sets a byte-range lock of the given type (`F_RDLCK`, `F_WRLCK` or
+/// `F_UNLCK`) on the given region with the given `fcntl` command. A length of
+/// zero extends the region indefinitely.
+fn set_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    kind: libc::c_int,
+    command: libc::c_int,
+) -> Result<(), Error> {
+    let (start, len) =
+        match (libc::off_t::try_from(offset), libc::off_t::try_from(len)) {
+            (Ok(start), Ok(len)) => (start, len),
+            _ => return Err(Error::from_raw_os_error(libc::EINVAL)),
+        };
+    let mut lock: libc::flock = unsafe { core::mem::zeroed() };
+    lock.l_type = kind as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = start;
+    lock.l_len = len;
+    // Must be zero for open file description locks.
+    lock.l_pid = 0;
+
+    let res = unsafe { libc::fcntl(fd, command, &lock) };
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Like `set_range`, but returns `false` if the region is locked by someone
+/// else.
+fn try_set_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    kind: libc::c_int,
+    command: libc::c_int,
+) -> Result<bool, Error> {
+    match set_range(fd, offset, len, kind, command) {
+        Ok(()) => Ok(true),
+        Err(Error::WouldBlock) => Ok(false),
+        Err(error) if error.raw_os_error() == Some(libc::EACCES) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Converts a lock mode into the corresponding `fcntl` lock type.
+fn range_kind(mode: LockMode) -> libc::c_int {
+    match mode {
+        LockMode::Shared => libc::F_RDLCK as libc::c_int,
+        LockMode::Exclusive => libc::F_WRLCK as libc::c_int,
+    }
+}
+
+/// Locks a region of the file, blocking until it is possible to lock.
+pub fn lock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<(), Error> {
+    set_range(fd, offset, len, range_kind(mode), range_command(true))
+}
+
+/// Tries to lock a region of the file, returning as soon as possible if
+/// already locked.
+pub fn try_lock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<bool, Error> {
+    try_set_range(fd, offset, len, range_kind(mode), range_command(false))
+}
+
+/// Unlocks a region of the file.
+pub fn unlock_range(fd: FileDesc, offset: u64, len: u64) -> Result<(), Error> {
+    let kind = libc::F_UNLCK as libc::c_int;
+    set_range(fd, offset, len, kind, range_command(false))
+}
+
+/// Locks a region of the file with a classic POSIX record lock, held per
+/// process, blocking until it is possible to lock.
+pub fn posix_lock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<(), Error> {
+    set_range(fd, offset, len, range_kind(mode), posix_command(true))
+}
+
+/// Tries to lock a region of the file with a classic POSIX record lock, held
+/// per process, returning as soon as possible if already locked.
+pub fn posix_try_lock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<bool, Error> {
+    try_set_range(fd, offset, len, range_kind(mode), posix_command(false))
+}
+
+/// Unlocks a region of the file locked with a classic POSIX record lock.
+pub fn posix_unlock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let kind = libc::F_UNLCK as libc::c_int;
+    set_range(fd, offset, len, kind, posix_command(false))
+}
+
+/// Converts a held shared lock into an exclusive one, blocking until possible.
+/// With `flock`, the conversion is not atomic: the shared lock is released
+/// before the exclusive lock is requested.
+pub fn upgrade(fd: FileDesc) -> Result<(), Error> {
+    lock(fd, LockMode::Exclusive)
+}
+
+/// Tries to convert a held shared lock into an exclusive one, returning as soon
+/// as possible if not possible. With `flock`, the shared lock is released
+/// before the exclusive lock is requested, and it is lost on failure.
+pub fn try_upgrade(fd: FileDesc) -> Result<bool, Error> {
+    try_lock(fd, LockMode::Exclusive)
+}
+
+/// Converts a held exclusive lock into a shared one. With `flock`, the
+/// conversion is not atomic: the exclusive lock is released before the shared
+/// lock is requested.
+pub fn downgrade(fd: FileDesc) -> Result<(), Error> {
+    lock(fd, LockMode::Shared)
+}
+
 /// Closes the file.
 pub fn close(fd: FileDesc) {
     unsafe { libc::close(fd) };
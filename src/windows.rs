@@ -12,33 +12,67 @@ use winapi::um::{
 #[cfg(feature = "std")]
 use std::{ffi, os::windows::ffi::OsStrExt};
 
-use crate::{EitherOsStr, IntoOsString, ToOsStr};
+use crate::{
+    EitherOsStr,
+    Error,
+    IntoOsString,
+    LockFileOptions,
+    LockMode,
+    ToOsStr,
+};
 use core::{
     convert::TryFrom,
     fmt,
-    mem::{transmute, MaybeUninit},
+    mem::{self, transmute, MaybeUninit},
     ptr::{self, NonNull},
     slice,
 };
 use winapi::{
     shared::{
-        minwindef::{DWORD, FALSE, LPCVOID, LPVOID, TRUE},
-        winerror::{ERROR_INVALID_DATA, ERROR_LOCK_VIOLATION},
+        minwindef::{DWORD, FALSE, FILETIME, LPCVOID, LPVOID, TRUE},
+        winerror::{
+            ERROR_ACCESS_DENIED,
+            ERROR_ALREADY_EXISTS,
+            ERROR_CALL_NOT_IMPLEMENTED,
+            ERROR_FILE_EXISTS,
+            ERROR_FILE_NOT_FOUND,
+            ERROR_HANDLE_EOF,
+            ERROR_INVALID_HANDLE,
+            ERROR_INVALID_PARAMETER,
+            ERROR_LOCK_VIOLATION,
+            ERROR_NOT_SUPPORTED,
+            ERROR_OPERATION_ABORTED,
+            ERROR_PATH_NOT_FOUND,
+            ERROR_POSSIBLE_DEADLOCK,
+            ERROR_TIMEOUT,
+            WAIT_TIMEOUT,
+        },
     },
     um::{
         errhandlingapi::GetLastError,
         fileapi::{
+            CreateDirectoryW,
             CreateFileW,
+            DeleteFileW,
             FlushFileBuffers,
+            GetFileInformationByHandle,
+            GetFileTime,
             LockFileEx,
+            ReadFile,
+            RemoveDirectoryW,
             SetEndOfFile,
             SetFilePointer,
+            SetFilePointerEx,
             UnlockFileEx,
             WriteFile,
+            BY_HANDLE_FILE_INFORMATION,
+            CREATE_NEW,
             INVALID_SET_FILE_POINTER,
             OPEN_ALWAYS,
+            OPEN_EXISTING,
         },
         handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        libloaderapi::GetModuleFileNameA,
         minwinbase::{
             OVERLAPPED_u,
             LMEM_FIXED,
@@ -48,16 +82,37 @@ use winapi::{
             LPSECURITY_ATTRIBUTES,
             OVERLAPPED,
             SECURITY_ATTRIBUTES,
+            STILL_ACTIVE,
+        },
+        processthreadsapi::{
+            GetCurrentProcessId,
+            GetExitCodeProcess,
+            OpenProcess,
+        },
+        synchapi::{CreateEventW, Sleep, WaitForSingleObject},
+        sysinfoapi::GetSystemTimeAsFileTime,
+        winbase::{
+            GetComputerNameA,
+            LocalAlloc,
+            LocalFree,
+            MoveFileExW,
+            FILE_BEGIN,
+            FILE_CURRENT,
+            FILE_END,
+            FILE_FLAG_OPEN_REPARSE_POINT,
+            MOVEFILE_REPLACE_EXISTING,
+            WAIT_FAILED,
         },
-        processthreadsapi::GetCurrentProcessId,
-        synchapi::{CreateEventW, WaitForSingleObject},
-        winbase::{LocalAlloc, LocalFree, FILE_BEGIN, WAIT_FAILED},
         winnt::{
             FILE_SHARE_DELETE,
             FILE_SHARE_READ,
             FILE_SHARE_WRITE,
+            GENERIC_READ,
             GENERIC_WRITE,
             HANDLE,
+            LARGE_INTEGER,
+            LPSTR,
+            PROCESS_QUERY_LIMITED_INFORMATION,
             WCHAR,
         },
     },
@@ -69,70 +124,57 @@ pub type FileDesc = HANDLE;
 /// A type representing Process ID on Windows.
 pub type Pid = DWORD;
 
-#[cfg(feature = "std")]
-/// An IO error.
-pub type Error = std::io::Error;
-
-#[cfg(not(feature = "std"))]
-#[derive(Debug)]
-/// An IO error. Without std, you can only get a message or an OS error code.
-pub struct Error {
-    code: i32,
+/// Returns the last OS error code of this thread.
+pub fn last_error_code() -> i32 {
+    unsafe { GetLastError() as i32 }
 }
 
-#[cfg(not(feature = "std"))]
-impl Error {
-    /// Creates an error from a raw OS error code.
-    pub fn from_raw_os_error(code: i32) -> Self {
-        Self { code }
-    }
-
-    /// Creates an error from the last OS error code.
-    pub fn last_os_error() -> Error {
-        Self::from_raw_os_error(unsafe { GetLastError() } as i32)
-    }
-
-    /// Raw OS error code. Returns option for compatibility with std.
-    pub fn raw_os_error(&self) -> Option<i32> {
-        Some(self.code)
+/// Classifies a raw OS error code into an error.
+pub fn classify_error(code: i32) -> Error {
+    match code as DWORD {
+        ERROR_LOCK_VIOLATION => Error::WouldBlock,
+        ERROR_TIMEOUT | WAIT_TIMEOUT => Error::TimedOut,
+        ERROR_OPERATION_ABORTED => Error::Interrupted,
+        ERROR_POSSIBLE_DEADLOCK => Error::Deadlock,
+        ERROR_NOT_SUPPORTED | ERROR_CALL_NOT_IMPLEMENTED => Error::NotSupported,
+        _ => Error::Os(code),
     }
 }
 
+/// Formats the message of a raw OS error code.
 #[cfg(not(feature = "std"))]
-impl fmt::Display for Error {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let mut buf: LPWSTR = ptr::null_mut();
-        let res = unsafe {
-            FormatMessageW(
-                FORMAT_MESSAGE_ALLOCATE_BUFFER
-                    | FORMAT_MESSAGE_FROM_SYSTEM
-                    | FORMAT_MESSAGE_IGNORE_INSERTS,
-                ptr::null_mut(),
-                self.code as DWORD,
-                LANG_USER_DEFAULT as DWORD,
-                &mut buf as *mut LPWSTR as LPWSTR,
-                0,
-                ptr::null_mut(),
-            )
-        };
+pub fn fmt_error(code: i32, fmt: &mut fmt::Formatter) -> fmt::Result {
+    let mut buf: LPWSTR = ptr::null_mut();
+    let res = unsafe {
+        FormatMessageW(
+            FORMAT_MESSAGE_ALLOCATE_BUFFER
+                | FORMAT_MESSAGE_FROM_SYSTEM
+                | FORMAT_MESSAGE_IGNORE_INSERTS,
+            ptr::null_mut(),
+            code as DWORD,
+            LANG_USER_DEFAULT as DWORD,
+            &mut buf as *mut LPWSTR as LPWSTR,
+            0,
+            ptr::null_mut(),
+        )
+    };
 
-        if res == 0 {
-            write!(fmt, "error getting error message")?;
-        } else {
-            {
-                let slice = unsafe {
-                    slice::from_raw_parts(buf as *const WCHAR, res as usize)
-                };
-                let str = unsafe { OsStr::from_slice(slice) };
-                write!(fmt, "{}", str)?;
-            }
-            unsafe {
-                LocalFree(buf as LPVOID);
-            }
+    if res == 0 {
+        write!(fmt, "error getting error message")?;
+    } else {
+        {
+            let slice = unsafe {
+                slice::from_raw_parts(buf as *const WCHAR, res as usize)
+            };
+            let str = unsafe { OsStr::from_slice(slice) };
+            write!(fmt, "{}", str)?;
+        }
+        unsafe {
+            LocalFree(buf as LPVOID);
         }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 /// Owned allocation of an OS-native string.
@@ -175,11 +217,26 @@ impl OsStr {
         transmute(slice)
     }
 
+    /// Returns the empty string.
+    pub(crate) fn empty() -> &'static Self {
+        unsafe { Self::from_slice(&[0]) }
+    }
+
     /// Chars iterator w/o the null char
     fn chars_wo_null<'a>(&'a self) -> Chars<'a> {
         let (_null, chars) = self.chars.split_last().unwrap();
         Chars { inner: chars.iter() }
     }
+
+    /// Allocates a copy of this string with the given suffix appended.
+    pub fn with_suffix(&self, suffix: &str) -> Result<OsString, Error> {
+        let (_null, chars) = self.chars.split_last().unwrap();
+        unsafe {
+            make_os_string(|| {
+                chars.iter().copied().chain(suffix.encode_utf16())
+            })
+        }
+    }
 }
 
 impl fmt::Debug for OsStr {
@@ -258,7 +315,7 @@ where
     let mut prev_zero = false;
     for ch in make_iter() {
         if prev_zero {
-            Err(Error::from_raw_os_error(ERROR_INVALID_DATA as i32))?;
+            return Err(Error::InvalidPath);
         }
         if ch == 0 {
             prev_zero = true;
@@ -352,8 +409,9 @@ fn make_security_attributes() -> SECURITY_ATTRIBUTES {
     }
 }
 
-/// Creates an overlapped struct to be used with this implementation.
-fn make_overlapped() -> Result<OVERLAPPED, Error> {
+/// Creates an overlapped struct at the given offset to be used with this
+/// implementation.
+fn make_overlapped_at(offset: u64) -> Result<OVERLAPPED, Error> {
     Ok(OVERLAPPED {
         Internal: 0,
         InternalHigh: 0,
@@ -361,8 +419,8 @@ fn make_overlapped() -> Result<OVERLAPPED, Error> {
             let mut uninit = MaybeUninit::<OVERLAPPED_u>::uninit();
             unsafe {
                 let refer = (&mut *uninit.as_mut_ptr()).s_mut();
-                refer.Offset = DWORD::MAX - 1;
-                refer.OffsetHigh = DWORD::MAX - 1;
+                refer.Offset = offset as DWORD;
+                refer.OffsetHigh = (offset >> 32) as DWORD;
                 uninit.assume_init()
             }
         },
@@ -370,24 +428,212 @@ fn make_overlapped() -> Result<OVERLAPPED, Error> {
     })
 }
 
+/// Error reported for operations not supported on the current platform.
+pub fn unsupported() -> Error {
+    Error::NotSupported
+}
+
+/// Returns the error reported when operating on a lock file which is not open.
+pub fn not_open() -> Error {
+    Error::from_raw_os_error(ERROR_INVALID_HANDLE as i32)
+}
+
 /// Returns the ID of the current process.
 pub fn pid() -> Pid {
     unsafe { GetCurrentProcessId() }
 }
 
-/// Opens a file with only purpose of locking it. Creates it if it does not
-/// exist. Path must not contain a nul-byte in the middle, but a nul-byte in the
-/// end (and only in the end) is allowed, which in this case no extra allocation
-/// will be made. Otherwise, an extra allocation is made.
-pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
+/// Writes the host name into the given buffer, returning its length, or `None`
+/// if it is not available.
+pub fn hostname(buf: &mut [u8]) -> Option<usize> {
+    let mut size = buf.len() as DWORD;
+    let res = unsafe { GetComputerNameA(buf.as_mut_ptr() as LPSTR, &mut size) };
+    if res == 0 || size == 0 {
+        None
+    } else {
+        Some(size as usize)
+    }
+}
+
+/// Writes the path of the current executable into the given buffer, returning
+/// its length, or `None` if it is not available.
+pub fn exe_path(buf: &mut [u8]) -> Option<usize> {
+    let len = unsafe {
+        GetModuleFileNameA(
+            ptr::null_mut(),
+            buf.as_mut_ptr() as LPSTR,
+            buf.len() as DWORD,
+        )
+    };
+    if len == 0 || len as usize >= buf.len() {
+        None
+    } else {
+        Some(len as usize)
+    }
+}
+
+/// Returns the start time of the current process, which is not recorded on
+/// this platform.
+pub fn start_time() -> Option<u64> {
+    None
+}
+
+/// Returns the current time, in seconds since the Unix epoch.
+pub fn now_unix() -> u64 {
+    let mut time = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    unsafe { GetSystemTimeAsFileTime(&mut time) };
+    filetime_to_unix(time)
+}
+
+/// Converts a file time into seconds since the Unix epoch.
+fn filetime_to_unix(time: FILETIME) -> u64 {
+    /// Seconds between the Windows epoch (1601) and the Unix epoch (1970).
+    const EPOCH_DIFF: u64 = 11_644_473_600;
+
+    let ticks =
+        u64::from(time.dwHighDateTime) << 32 | u64::from(time.dwLowDateTime);
+    (ticks / 10_000_000).saturating_sub(EPOCH_DIFF)
+}
+
+/// Opens a file with only purpose of locking it, as configured by the given
+/// options. Path must not contain a nul-byte in the middle, but a nul-byte in
+/// the end (and only in the end) is allowed, which in this case no extra
+/// allocation will be made. Otherwise, an extra allocation is made.
+/// The mode is ignored.
+pub fn open_with(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<FileDesc, Error> {
+    let access = if options.read_only {
+        GENERIC_READ
+    } else {
+        GENERIC_READ | GENERIC_WRITE
+    };
+    let disposition = if options.create_new {
+        CREATE_NEW
+    } else if options.create {
+        OPEN_ALWAYS
+    } else {
+        OPEN_EXISTING
+    };
+    let handle = unsafe { create_file(path, access, disposition, options) };
+
+    if handle != INVALID_HANDLE_VALUE {
+        Ok(handle)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Creates a new file and opens it for reading and writing, using the flags
+/// of the given options. Returns `None` if the file already exists.
+pub fn create_new(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<Option<FileDesc>, Error> {
+    let access = GENERIC_READ | GENERIC_WRITE;
+    let handle = unsafe { create_file(path, access, CREATE_NEW, options) };
+
+    if handle != INVALID_HANDLE_VALUE {
+        return Ok(Some(handle));
+    }
+    match unsafe { GetLastError() } {
+        // A file pending deletion cannot be created either.
+        ERROR_FILE_EXISTS | ERROR_ACCESS_DENIED => Ok(None),
+        err => Err(Error::from_raw_os_error(err as i32)),
+    }
+}
+
+/// Calls `CreateFileW` with the given access and disposition, adding the
+/// inheritance and flags of the given options.
+unsafe fn create_file(
+    path: &OsStr,
+    access: DWORD,
+    disposition: DWORD,
+    options: &LockFileOptions,
+) -> HANDLE {
+    let mut security = make_security_attributes();
+    if !options.cloexec {
+        security.nLength = mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD;
+        security.bInheritHandle = TRUE;
+    }
+    let flags = if options.nofollow { FILE_FLAG_OPEN_REPARSE_POINT } else { 0 };
+    CreateFileW(
+        path.chars.as_ptr(),
+        access,
+        FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+        &mut security as LPSECURITY_ATTRIBUTES,
+        disposition,
+        flags,
+        ptr::null_mut(),
+    )
+}
+
+/// Creates a new directory. Returns `false` if it already exists. The
+/// permissions of the given options are ignored, as on files.
+pub fn create_dir(
+    path: &OsStr,
+    _options: &LockFileOptions,
+) -> Result<bool, Error> {
+    let mut security = make_security_attributes();
+    let res = unsafe {
+        CreateDirectoryW(
+            path.chars.as_ptr(),
+            &mut security as LPSECURITY_ATTRIBUTES,
+        )
+    };
+    if res != 0 {
+        return Ok(true);
+    }
+    match unsafe { GetLastError() } {
+        ERROR_ALREADY_EXISTS => Ok(false),
+        err => Err(Error::from_raw_os_error(err as i32)),
+    }
+}
+
+/// Removes the empty directory at the given path.
+pub fn remove_dir(path: &OsStr) -> Result<(), Error> {
+    let res = unsafe { RemoveDirectoryW(path.chars.as_ptr()) };
+    if res != 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Removes the file at the given path. Open handles to it remain valid, but
+/// the path cannot be created again until they are all closed.
+pub fn remove(path: &OsStr) -> Result<(), Error> {
+    let res = unsafe { DeleteFileW(path.chars.as_ptr()) };
+    if res != 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Syncs the directory containing the given path to disk. Does nothing, since
+/// NTFS journals directory entries, and directories cannot be flushed without
+/// administrative rights.
+pub fn sync_parent(_path: &OsStr) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Sleeps for the given number of milliseconds.
+pub fn sleep_ms(millis: u32) {
+    unsafe { Sleep(millis) }
+}
+
+/// Opens an existing file for reading only. Does not create it.
+pub fn open_read(path: &OsStr) -> Result<FileDesc, Error> {
     let mut security = make_security_attributes();
     let handle = unsafe {
         CreateFileW(
             path.chars.as_ptr(),
-            GENERIC_WRITE,
+            GENERIC_READ,
             FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
             &mut security as LPSECURITY_ATTRIBUTES,
-            OPEN_ALWAYS,
+            OPEN_EXISTING,
             0,
             ptr::null_mut(),
         )
@@ -400,24 +646,258 @@ pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
     }
 }
 
-/// Writes data into the given open file.
-pub fn write(handle: FileDesc, bytes: &[u8]) -> Result<(), Error> {
-    let result = unsafe {
-        WriteFile(
-            handle,
-            bytes.as_ptr() as LPCVOID,
-            bytes.len() as DWORD,
-            ptr::null_mut(),
-            ptr::null_mut(),
-        )
+/// Opens an existing file for reading only, returning `None` if it does not
+/// exist.
+pub fn open_read_existing(path: &OsStr) -> Result<Option<FileDesc>, Error> {
+    match open_read(path) {
+        Ok(handle) => Ok(Some(handle)),
+        Err(error) => match error.raw_os_error().map(|code| code as DWORD) {
+            Some(ERROR_FILE_NOT_FOUND) | Some(ERROR_PATH_NOT_FOUND) => Ok(None),
+            _ => Err(error),
+        },
+    }
+}
+
+/// Reads data from the given open file at the given offset. Returns how many
+/// bytes were read, which is less than the buffer's size only at the end of
+/// the file.
+pub fn read_at(
+    handle: FileDesc,
+    mut offset: u64,
+    buf: &mut [u8],
+) -> Result<usize, Error> {
+    let mut total = 0;
+    while total < buf.len() {
+        let mut overlapped = OVERLAPPED {
+            Internal: 0,
+            InternalHigh: 0,
+            u: {
+                let mut uninit = MaybeUninit::<OVERLAPPED_u>::uninit();
+                unsafe {
+                    let refer = (&mut *uninit.as_mut_ptr()).s_mut();
+                    refer.Offset = offset as DWORD;
+                    refer.OffsetHigh = (offset >> 32) as DWORD;
+                    uninit.assume_init()
+                }
+            },
+            hEvent: ptr::null_mut(),
+        };
+        let mut read: DWORD = 0;
+        let res = unsafe {
+            ReadFile(
+                handle,
+                buf[total ..].as_mut_ptr() as LPVOID,
+                (buf.len() - total) as DWORD,
+                &mut read,
+                &mut overlapped as LPOVERLAPPED,
+            )
+        };
+        if res == 0 {
+            let err = unsafe { GetLastError() };
+            if err == ERROR_HANDLE_EOF {
+                break;
+            }
+            return Err(Error::from_raw_os_error(err as i32));
+        }
+        if read == 0 {
+            break;
+        }
+        total += read as usize;
+        offset += read as u64;
+    }
+
+    Ok(total)
+}
+
+/// Reads data from the given open file at the file's cursor, advancing it.
+/// Returns how many bytes were read, which is less than the buffer's size only
+/// at the end of the file.
+pub fn read(handle: FileDesc, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut total = 0;
+    while total < buf.len() {
+        let mut read: DWORD = 0;
+        let res = unsafe {
+            ReadFile(
+                handle,
+                buf[total ..].as_mut_ptr() as LPVOID,
+                (buf.len() - total) as DWORD,
+                &mut read,
+                ptr::null_mut(),
+            )
+        };
+        if res == 0 {
+            let err = unsafe { GetLastError() };
+            if err == ERROR_HANDLE_EOF {
+                break;
+            }
+            return Err(Error::from_raw_os_error(err as i32));
+        }
+        if read == 0 {
+            break;
+        }
+        total += read as usize;
+    }
+
+    Ok(total)
+}
+
+/// Moves the cursor of the given open file, returning its new position from
+/// the start of the file.
+#[cfg(feature = "std")]
+pub fn seek(
+    handle: FileDesc,
+    position: std::io::SeekFrom,
+) -> Result<u64, Error> {
+    let (offset, method) = match position {
+        std::io::SeekFrom::Start(offset) => (offset as i64, FILE_BEGIN),
+        std::io::SeekFrom::Current(offset) => (offset, FILE_CURRENT),
+        std::io::SeekFrom::End(offset) => (offset, FILE_END),
     };
-    if result == 0 {
+    let mut distance: LARGE_INTEGER = unsafe { mem::zeroed() };
+    unsafe { *distance.QuadPart_mut() = offset };
+    let mut new_position: LARGE_INTEGER = unsafe { mem::zeroed() };
+    let res = unsafe {
+        SetFilePointerEx(handle, distance, &mut new_position, method)
+    };
+
+    if res != 0 {
+        Ok(unsafe { *new_position.QuadPart() } as u64)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Returns whether a process with the given ID is still running.
+pub fn is_alive(pid: Pid) -> Result<bool, Error> {
+    let process =
+        unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if process.is_null() {
+        return match unsafe { GetLastError() } {
+            // The process exists, but belongs to someone else.
+            ERROR_ACCESS_DENIED => Ok(true),
+            ERROR_INVALID_PARAMETER => Ok(false),
+            err => Err(Error::from_raw_os_error(err as i32)),
+        };
+    }
+    let drop_handle = DropHandle { handle: process };
+    let mut code: DWORD = 0;
+    let res = unsafe { GetExitCodeProcess(process, &mut code) };
+    let ret = if res == 0 {
         Err(Error::last_os_error())
     } else {
+        Ok(code == STILL_ACTIVE)
+    };
+    drop(drop_handle);
+    ret
+}
+
+/// Queries the volume and index identifying the given open file.
+fn file_id(handle: FileDesc) -> Result<(DWORD, DWORD, DWORD), Error> {
+    let mut info = MaybeUninit::<BY_HANDLE_FILE_INFORMATION>::uninit();
+    let res = unsafe { GetFileInformationByHandle(handle, info.as_mut_ptr()) };
+    if res == 0 {
+        return Err(Error::last_os_error());
+    }
+    let info = unsafe { info.assume_init() };
+    Ok((info.dwVolumeSerialNumber, info.nFileIndexHigh, info.nFileIndexLow))
+}
+
+/// Returns whether the given path currently refers to the given open file.
+/// Returns `false` if the path does not exist.
+pub fn same_file(path: &OsStr, handle: FileDesc) -> Result<bool, Error> {
+    let mut security = make_security_attributes();
+    let path_handle = unsafe {
+        CreateFileW(
+            path.chars.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            &mut security as LPSECURITY_ATTRIBUTES,
+            OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        )
+    };
+    if path_handle == INVALID_HANDLE_VALUE {
+        return match unsafe { GetLastError() } {
+            ERROR_FILE_NOT_FOUND => Ok(false),
+            err => Err(Error::from_raw_os_error(err as i32)),
+        };
+    }
+    let drop_handle = DropHandle { handle: path_handle };
+    let ret = file_id(path_handle)
+        .and_then(|path_id| Ok(path_id == file_id(handle)?));
+    drop(drop_handle);
+    ret
+}
+
+#[cfg(feature = "std")]
+/// Returns the last modification time of the given open file, in seconds since
+/// the Unix epoch.
+pub fn modified_unix(handle: FileDesc) -> Result<u64, Error> {
+    let mut time = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    let res = unsafe {
+        GetFileTime(handle, ptr::null_mut(), ptr::null_mut(), &mut time)
+    };
+    if res == 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(filetime_to_unix(time))
+}
+
+/// Replaces the file at the destination path with the file at the source path.
+/// Fails if the replaced file is open without sharing deletion.
+pub fn rename(source: &OsStr, dest: &OsStr) -> Result<(), Error> {
+    let res = unsafe {
+        MoveFileExW(
+            source.chars.as_ptr(),
+            dest.chars.as_ptr(),
+            MOVEFILE_REPLACE_EXISTING,
+        )
+    };
+    if res != 0 {
         Ok(())
+    } else {
+        Err(Error::last_os_error())
     }
 }
 
+/// Renames the file or directory at the given source path, like [`rename`].
+/// Returns `false` if there is nothing at the source path.
+pub fn rename_existing(source: &OsStr, dest: &OsStr) -> Result<bool, Error> {
+    match rename(source, dest) {
+        Ok(()) => Ok(true),
+        Err(error) => match error.raw_os_error().map(|code| code as DWORD) {
+            Some(ERROR_FILE_NOT_FOUND) | Some(ERROR_PATH_NOT_FOUND) => {
+                Ok(false)
+            },
+            _ => Err(error),
+        },
+    }
+}
+
+/// Writes data into the given open file.
+pub fn write(handle: FileDesc, mut bytes: &[u8]) -> Result<(), Error> {
+    while !bytes.is_empty() {
+        let mut written: DWORD = 0;
+        let result = unsafe {
+            WriteFile(
+                handle,
+                bytes.as_ptr() as LPCVOID,
+                bytes.len() as DWORD,
+                &mut written,
+                ptr::null_mut(),
+            )
+        };
+        if result == 0 {
+            return Err(Error::last_os_error());
+        }
+        bytes = &bytes[written as usize ..];
+    }
+
+    Ok(())
+}
+
+/// Syncs the content of the given open file to disk.
 pub fn fsync(handle: FileDesc) -> Result<(), Error> {
     let result = unsafe { FlushFileBuffers(handle) };
     if result == 0 {
@@ -442,17 +922,62 @@ pub fn truncate(handle: FileDesc) -> Result<(), Error> {
     }
 }
 
+/// Converts a lock mode into the corresponding `LockFileEx` flags.
+fn lock_flags(mode: LockMode) -> DWORD {
+    match mode {
+        LockMode::Shared => 0,
+        LockMode::Exclusive => LOCKFILE_EXCLUSIVE_LOCK,
+    }
+}
+
+/// Offset of the region locked by whole-file locks, near the end of the 64-bit
+/// range, so they do not conflict with byte-range locks over actual data.
+const WHOLE_FILE_OFFSET: u64 = 0xFFFF_FFFE_FFFF_FFFE;
+
+/// Length of the region locked by whole-file locks.
+const WHOLE_FILE_LEN: u64 = 0x1_0000_0001;
+
+/// Splits the length of a region at the given offset into the low and high
+/// words expected by `LockFileEx`. A length of zero extends the region up to
+/// the whole-file lock region.
+fn split_len(offset: u64, len: u64) -> (DWORD, DWORD) {
+    let len =
+        if len == 0 { WHOLE_FILE_OFFSET.saturating_sub(offset) } else { len };
+    (len as DWORD, (len >> 32) as DWORD)
+}
+
 /// Tries to lock a file and blocks until it is possible to lock.
-pub fn lock(handle: FileDesc) -> Result<(), Error> {
-    let mut overlapped = make_overlapped()?;
+pub fn lock(handle: FileDesc, mode: LockMode) -> Result<(), Error> {
+    lock_range(handle, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN, mode)
+}
+
+/// Tries to lock a file but returns as soon as possible if already locked.
+pub fn try_lock(handle: FileDesc, mode: LockMode) -> Result<bool, Error> {
+    try_lock_range(handle, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN, mode)
+}
+
+/// Unlocks the file.
+pub fn unlock(handle: FileDesc) -> Result<(), Error> {
+    unlock_range(handle, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN)
+}
+
+/// Locks a region of the file, blocking until it is possible to lock.
+pub fn lock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<(), Error> {
+    let mut overlapped = make_overlapped_at(offset)?;
     let drop_handle = DropHandle { handle: overlapped.hEvent };
+    let (len_low, len_high) = split_len(offset, len);
     let res = unsafe {
         LockFileEx(
             handle,
-            LOCKFILE_EXCLUSIVE_LOCK,
+            lock_flags(mode),
             0,
-            1,
-            1,
+            len_low,
+            len_high,
             &mut overlapped as LPOVERLAPPED,
         )
     };
@@ -472,17 +997,24 @@ pub fn lock(handle: FileDesc) -> Result<(), Error> {
     ret
 }
 
-/// Tries to lock a file but returns as soon as possible if already locked.
-pub fn try_lock(handle: FileDesc) -> Result<bool, Error> {
-    let mut overlapped = make_overlapped()?;
+/// Tries to lock a region of the file, returning as soon as possible if
+/// already locked.
+pub fn try_lock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+    mode: LockMode,
+) -> Result<bool, Error> {
+    let mut overlapped = make_overlapped_at(offset)?;
     let drop_handle = DropHandle { handle: overlapped.hEvent };
+    let (len_low, len_high) = split_len(offset, len);
     let res = unsafe {
         LockFileEx(
             handle,
-            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            lock_flags(mode) | LOCKFILE_FAIL_IMMEDIATELY,
             0,
-            1,
-            1,
+            len_low,
+            len_high,
             &mut overlapped as LPOVERLAPPED,
         )
     };
@@ -507,12 +1039,24 @@ pub fn try_lock(handle: FileDesc) -> Result<bool, Error> {
     ret
 }
 
-/// Unlocks the file.
-pub fn unlock(handle: FileDesc) -> Result<(), Error> {
-    let mut overlapped = make_overlapped()?;
+/// Unlocks a region of the file. The region must match exactly a region
+/// previously locked.
+pub fn unlock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let mut overlapped = make_overlapped_at(offset)?;
     let drop_handle = DropHandle { handle: overlapped.hEvent };
+    let (len_low, len_high) = split_len(offset, len);
     let res = unsafe {
-        UnlockFileEx(handle, 0, 1, 1, &mut overlapped as LPOVERLAPPED)
+        UnlockFileEx(
+            handle,
+            0,
+            len_low,
+            len_high,
+            &mut overlapped as LPOVERLAPPED,
+        )
     };
 
     let ret = if res == TRUE {
@@ -530,6 +1074,30 @@ pub fn unlock(handle: FileDesc) -> Result<(), Error> {
     ret
 }
 
+/// Converts a held shared lock into an exclusive one, blocking until possible.
+/// `LockFileEx` cannot convert locks, so the shared lock is released before the
+/// exclusive lock is requested.
+pub fn upgrade(handle: FileDesc) -> Result<(), Error> {
+    unlock(handle)?;
+    lock(handle, LockMode::Exclusive)
+}
+
+/// Tries to convert a held shared lock into an exclusive one, returning as soon
+/// as possible if not possible. The shared lock is released before the
+/// exclusive lock is requested, and it is lost on failure.
+pub fn try_upgrade(handle: FileDesc) -> Result<bool, Error> {
+    unlock(handle)?;
+    try_lock(handle, LockMode::Exclusive)
+}
+
+/// Converts a held exclusive lock into a shared one. A handle holding an
+/// exclusive lock may also acquire a shared lock on the same range, and the
+/// first unlock releases the exclusive one, so the conversion is atomic.
+pub fn downgrade(handle: FileDesc) -> Result<(), Error> {
+    lock(handle, LockMode::Shared)?;
+    unlock(handle)
+}
+
 /// Closes the file.
 pub fn close(handle: FileDesc) {
     unsafe {